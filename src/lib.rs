@@ -0,0 +1,5703 @@
+//! Find recently created files, such as downloads, and move them somewhere else.
+//!
+//! [`read_config`] loads the configuration, [`find_recent_files`] scans its source
+//! directories and [`move_file`] moves one of the files found. The `m` binary is a
+//! prompt on top of these.
+
+use chrono::format::{Item, StrftimeItems};
+use clap::ValueEnum;
+use crossterm::style::{StyledContent, Stylize};
+use flate2::read::GzDecoder;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::Gitignore;
+use indicatif::{ProgressBar, ProgressStyle};
+use inquire::Select;
+use log::{debug, trace};
+use rayon::prelude::*;
+use regex::{RegexSet, RegexSetBuilder};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once, OnceLock};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use unicode_width::UnicodeWidthStr;
+
+/// Suffixes browsers and download managers give files until the download completes
+const INCOMPLETE_SUFFIXES: &[&str] = &[".crdownload", ".part", ".partial", ".opdownload"];
+
+/// Print informational output unless the first argument, the `quiet` setting, is
+/// true. Errors, the `--json` and `--list` output, dry runs and previews are printed
+/// regardless.
+macro_rules! report {
+    ($quiet:expr, $($arg:tt)*) => {
+        if !$quiet {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Print a warning, or another notice that is not part of the output, on stderr so
+/// stdout only carries the `--json` and `--list` output. Left out when `quiet`.
+macro_rules! warning {
+    ($quiet:expr, $($arg:tt)*) => {
+        if !$quiet {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// The settings from the config file, see the README for what each field does
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct Config {
+    #[serde(default)]
+    pub source_dir: Vec<SourceDir>,
+    // A text file with more source directories, one per line
+    #[serde(default)]
+    pub source_list_file: Option<String>,
+    pub time_limit: TimeLimit,
+    pub black_list: Vec<String>,
+    #[serde(default)]
+    pub black_list_ignore_case: bool,
+    #[serde(default)]
+    pub black_list_regex: Vec<String>,
+    #[serde(default)]
+    pub white_list: Vec<String>,
+    #[serde(default)]
+    pub skip_dirs: Vec<String>,
+    #[serde(default = "default_target_dir")]
+    pub target_dir: String,
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    // Content types such as "image/*" or "application/pdf", sniffed from the first bytes
+    #[serde(default)]
+    pub mime_allow: Vec<String>,
+    #[serde(default)]
+    pub mime_deny: Vec<String>,
+    // Only files whose name contains this, ignoring case
+    #[serde(default)]
+    pub name_contains: Option<String>,
+    // Human readable sizes such as "10MB", unset or "0" means no bound
+    #[serde(default)]
+    pub min_size: Option<String>,
+    #[serde(default)]
+    pub max_size: Option<String>,
+    // None recurses without limit
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+    #[serde(default)]
+    pub sort_by: SortBy,
+    #[serde(default)]
+    pub reverse: bool,
+    #[serde(default)]
+    pub group_dirs: GroupDirs,
+    // 0 shows every matching file
+    #[serde(default)]
+    pub limit: usize,
+    // A glyph for the kind of file before each name, which needs a font with emoji
+    #[serde(default)]
+    pub show_icons: bool,
+    // Rows of the file list, unset fits the list to the terminal
+    #[serde(default)]
+    pub page_size: Option<usize>,
+    // Ask for a filter first when more files than this match
+    #[serde(default)]
+    pub refine_threshold: Option<usize>,
+    // Files at least this old are highlighted in the list
+    #[serde(default)]
+    pub warn_age: Option<TimeLimit>,
+    // Files last modified longer ago than this are left out, whenever they were created
+    #[serde(default)]
+    pub max_stale: Option<TimeLimit>,
+    #[serde(default)]
+    pub time_format: TimeFormat,
+    // Columns of the list, with {time}, {size}, {name}, {path} and {ext}
+    #[serde(default = "default_display_format")]
+    pub display_format: String,
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    #[serde(default)]
+    pub respect_ignore: bool,
+    #[serde(default)]
+    pub size_units: SizeUnits,
+    // Leave out success messages, summaries and warnings, like --quiet
+    #[serde(default)]
+    pub quiet: bool,
+    // Only ASCII in icons and progress bars, like --ascii
+    #[serde(default)]
+    pub ascii: bool,
+    #[serde(default)]
+    pub color: ColorMode,
+    // Only supported on macOS, which records download origins
+    #[serde(default)]
+    pub from_contains: Vec<String>,
+    #[serde(default)]
+    pub include_dirs: bool,
+    // Also list and descend into names starting with a dot
+    #[serde(default)]
+    pub include_hidden: bool,
+    #[serde(default)]
+    pub time_basis: TimeSource,
+    #[serde(default)]
+    pub time_match: Option<TimeMatch>,
+    #[serde(default)]
+    pub remember_destination: bool,
+    #[serde(default)]
+    pub dedup_by: DedupBy,
+    #[serde(default)]
+    pub show_relative_path: bool,
+    #[serde(default)]
+    pub group_by_age: bool,
+    // Leave out downloads in progress
+    #[serde(default)]
+    pub skip_incomplete: bool,
+    // Leave out zero-byte files, such as placeholders left by browsers
+    #[serde(default)]
+    pub skip_empty: bool,
+    // Only zero-byte files, set by --only-empty for cleaning them up
+    #[serde(skip)]
+    pub only_empty: bool,
+    // Reuse the listings of directories that have not changed since the last scan
+    #[serde(default)]
+    pub scan_cache: bool,
+    // Subdirectories of the target for files with these extensions, e.g. "Pictures": ["png"]
+    #[serde(default)]
+    pub type_routing: BTreeMap<String, Vec<String>>,
+    // Subdirectory of the target named after the file's date, as a chrono format string
+    #[serde(default)]
+    pub date_subdir: Option<String>,
+    // Transfers failing with a transient error are retried this many times, waiting
+    // `retry_delay_ms` before the first retry and twice as long before each next one
+    #[serde(default)]
+    pub retry_count: u32,
+    #[serde(default = "default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+    // Shell command run after each move, with {src}, {dst} and {name} filled in
+    #[serde(default)]
+    pub post_move_hook: Option<String>,
+    // Desktop notification for each new file in watch mode
+    #[serde(default)]
+    pub notify: bool,
+    #[serde(default)]
+    pub destinations: Vec<String>,
+    // More directories each file is copied to besides the target directory
+    #[serde(default)]
+    pub fanout: Vec<String>,
+}
+
+/// Where `include_dirs` directories go in the sorted list
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupDirs {
+    #[default]
+    Mixed,
+    First,
+    Last,
+}
+
+/// Order of the file list, each defaulting to the most useful end first
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    /// Newest first
+    #[default]
+    Time,
+    /// Largest first
+    Size,
+    /// Alphabetical
+    Name,
+}
+
+/// A source directory, either a plain path using the global settings or an object
+/// overriding some of them for that directory
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SourceDir {
+    Path(String),
+    Detailed {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        time_limit: Option<TimeLimit>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        black_list: Option<Vec<String>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        extensions: Option<Vec<String>>,
+    },
+}
+
+impl SourceDir {
+    pub fn path(&self) -> &str {
+        match self {
+            SourceDir::Path(path) | SourceDir::Detailed { path, .. } => path,
+        }
+    }
+
+    pub fn time_limit(&self, config: &Config) -> TimeLimit {
+        match self {
+            SourceDir::Detailed {
+                time_limit: Some(time_limit),
+                ..
+            } => *time_limit,
+            _ => config.time_limit,
+        }
+    }
+
+    pub fn black_list<'a>(&'a self, config: &'a Config) -> &'a [String] {
+        match self {
+            SourceDir::Detailed {
+                black_list: Some(black_list),
+                ..
+            } => black_list,
+            _ => &config.black_list,
+        }
+    }
+
+    pub fn extensions<'a>(&'a self, config: &'a Config) -> &'a [String] {
+        match self {
+            SourceDir::Detailed {
+                extensions: Some(extensions),
+                ..
+            } => extensions,
+            _ => &config.extensions,
+        }
+    }
+
+    pub fn clear_time_limit(&mut self) {
+        if let SourceDir::Detailed { time_limit, .. } = self {
+            *time_limit = None;
+        }
+    }
+
+    pub fn clear_extensions(&mut self) {
+        if let SourceDir::Detailed { extensions, .. } = self {
+            *extensions = None;
+        }
+    }
+}
+
+/// Length of the time window, stored in seconds.
+///
+/// Deserializes from a bare number of minutes (the original format) or a duration
+/// string such as "90m", "2h" or "3d".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeLimit(u64);
+
+impl TimeLimit {
+    /// No time window at all, every file qualifies whatever its age
+    pub const UNLIMITED: TimeLimit = TimeLimit(u64::MAX);
+
+    /// None when the number of seconds would not fit
+    pub fn from_minutes(minutes: u64) -> Option<Self> {
+        minutes.checked_mul(60).map(TimeLimit)
+    }
+
+    pub fn is_unlimited(self) -> bool {
+        self == TimeLimit::UNLIMITED
+    }
+
+    pub fn seconds(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for TimeLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Use the largest unit the window divides into evenly
+        const UNITS: &[(u64, &str)] = &[(86400, "day"), (3600, "hour"), (60, "minute")];
+        let (size, name) = UNITS
+            .iter()
+            .find(|(size, _)| self.0.is_multiple_of(*size))
+            .copied()
+            .unwrap_or((1, "second"));
+        let count = self.0 / size;
+        write!(f, "{} {}{}", count, name, if count == 1 { "" } else { "s" })
+    }
+}
+
+impl Serialize for TimeLimit {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.0.is_multiple_of(60) {
+            serializer.serialize_u64(self.0 / 60)
+        } else {
+            serializer.serialize_str(&format!("{}s", self.0))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeLimit {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TimeLimitVisitor;
+
+        impl serde::de::Visitor<'_> for TimeLimitVisitor {
+            type Value = TimeLimit;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a number of minutes or a duration such as \"2h\"")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, minutes: u64) -> Result<TimeLimit, E> {
+                TimeLimit::from_minutes(minutes).ok_or_else(|| {
+                    E::custom(format!(
+                        "{} minutes is too long, use at most {} or --all for no limit",
+                        minutes,
+                        u64::MAX / 60
+                    ))
+                })
+            }
+
+            // TOML integers are signed
+            fn visit_i64<E: serde::de::Error>(self, minutes: i64) -> Result<TimeLimit, E> {
+                let minutes = u64::try_from(minutes)
+                    .map_err(|_| E::invalid_value(serde::de::Unexpected::Signed(minutes), &self))?;
+                self.visit_u64(minutes)
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<TimeLimit, E> {
+                parse_time_limit(value).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(TimeLimitVisitor)
+    }
+}
+
+/// How file times are shown in the list
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeFormat {
+    /// Age such as "3m ago"
+    Relative,
+    /// Local time of day as HH:MM
+    #[default]
+    Clock,
+    /// Full local date and time
+    Iso,
+}
+
+/// Totals from a scan, shown above the file list
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct ScanStats {
+    pub dirs_walked: usize,
+    pub files_matched: usize,
+    pub total_size: u64,
+    pub time_limit: TimeLimit,
+    pub size_units: SizeUnits,
+}
+
+impl fmt::Display for ScanStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} file{}, {}, ",
+            self.files_matched,
+            if self.files_matched == 1 { "" } else { "s" },
+            format_size(self.total_size, self.size_units),
+        )?;
+        if self.time_limit.is_unlimited() {
+            write!(f, "any age")?;
+        } else {
+            write!(f, "last {}", self.time_limit)?;
+        }
+        write!(
+            f,
+            " across {} dir{}",
+            self.dirs_walked,
+            if self.dirs_walked == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// Error type for the scan, which runs across threads
+type ScanError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Unit system used to show and parse file sizes
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeUnits {
+    /// 1024-based KB, MB, GB
+    #[default]
+    Binary,
+    /// 1000-based kB, MB, GB
+    Si,
+}
+
+impl SizeUnits {
+    pub fn base(self) -> f64 {
+        match self {
+            SizeUnits::Binary => 1024.0,
+            SizeUnits::Si => 1000.0,
+        }
+    }
+
+    pub fn suffixes(self) -> &'static [&'static str] {
+        match self {
+            SizeUnits::Binary => &["B", "KB", "MB", "GB", "TB"],
+            SizeUnits::Si => &["B", "kB", "MB", "GB", "TB"],
+        }
+    }
+}
+
+/// How files found more than once are collapsed in the list
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupBy {
+    /// Show every file
+    #[default]
+    None,
+    /// Files with the same name
+    Name,
+    /// Files with identical contents
+    Content,
+}
+
+/// Which of a file's timestamps must fall within the time limit, instead of the
+/// `time_basis` one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeMatch {
+    /// The creation or the modification time
+    Any,
+    /// Both the creation and the modification time
+    All,
+}
+
+/// Whether output is colored
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// Only when writing to a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && io::stdout().is_terminal()
+            }
+        }
+    }
+
+    /// Turn colors on or off for the rest of the run. Only the first choice
+    /// counts: once set, or once `styled` has used the default, later calls
+    /// keep the existing setting.
+    pub fn apply(self) {
+        let enabled = *COLOR_ENABLED.get_or_init(|| self.enabled());
+        // The prompts are drawn by crossterm too
+        crossterm::style::Colored::set_ansi_color_disabled(!enabled);
+    }
+}
+
+// Set once from the config, commands running before it is read use `auto`
+static COLOR_ENABLED: OnceLock<bool> = OnceLock::new();
+
+/// Render styled output, dropping the styling when colors are off
+pub fn styled<D: fmt::Display>(content: StyledContent<D>) -> String {
+    if *COLOR_ENABLED.get_or_init(|| ColorMode::Auto.enabled()) {
+        content.to_string()
+    } else {
+        content.content().to_string()
+    }
+}
+
+/// Per-scan limits derived once from the config
+struct ScanFilter {
+    // The source directory this scan started from
+    root: PathBuf,
+    quiet: bool,
+    now: u64,
+    not_before: u64,
+    black_list: PatternSet,
+    black_list_regex: RegexSet,
+    white_list: PatternSet,
+    skip_dirs: PatternSet,
+    extensions: Vec<String>,
+    // Lowercased `name_contains`
+    name_contains: Option<String>,
+    min_size: u64,
+    max_size: u64,
+    // Canonical paths of scanned directories, used to break symlink loops
+    visited: Mutex<HashSet<PathBuf>>,
+    dirs_walked: AtomicUsize,
+    cache: Option<Arc<ScanCache>>,
+    // The times of the `limit` newest files found so far, oldest on top, when the
+    // list is cut to the newest `limit` files
+    newest: Option<Mutex<BinaryHeap<Reverse<u64>>>>,
+}
+
+/// Directory listings from the previous scan, and the ones collected by this scan
+/// to be saved for the next
+#[derive(Default)]
+struct ScanCache {
+    previous: HashMap<PathBuf, CachedDir>,
+    current: Mutex<HashMap<PathBuf, CachedDir>>,
+}
+
+/// The names in a directory, valid as long as the directory's modification time is
+/// unchanged. Sizes and times are not kept, as writing to a file leaves its directory's
+/// time alone.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct CachedDir {
+    modified: SystemTime,
+    names: Vec<String>,
+}
+
+/// What the scan needs to know about a directory entry
+#[derive(Debug, Clone)]
+struct EntryInfo {
+    name: String,
+    path: PathBuf,
+    is_symlink: bool,
+    is_dir: bool,
+    is_file: bool,
+    len: u64,
+    created: Option<SystemTime>,
+    modified: Option<SystemTime>,
+    accessed: Option<SystemTime>,
+}
+
+impl EntryInfo {
+    fn new(path: PathBuf, metadata: &fs::Metadata) -> Self {
+        EntryInfo {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            path,
+            is_symlink: metadata.is_symlink(),
+            is_dir: metadata.is_dir(),
+            is_file: metadata.is_file(),
+            len: metadata.len(),
+            created: metadata.created().ok(),
+            modified: metadata.modified().ok(),
+            accessed: metadata.accessed().ok(),
+        }
+    }
+}
+
+fn default_target_dir() -> String {
+    ".".to_string()
+}
+
+fn default_retry_delay_ms() -> u64 {
+    500
+}
+
+fn default_display_format() -> String {
+    "{time}   {size}   {name}".to_string()
+}
+
+/// A completed move, recorded so it can be undone
+#[derive(Debug, Deserialize, Serialize)]
+struct HistoryEntry {
+    source: PathBuf,
+    destination: PathBuf,
+}
+
+/// File format of the configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Looked for in this order when no config file is given
+    pub const ALL: [ConfigFormat; 3] = [ConfigFormat::Toml, ConfigFormat::Yaml, ConfigFormat::Json];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Yaml => "yaml",
+            ConfigFormat::Json => "json",
+        }
+    }
+
+    /// The format of a config file by its extension, JSON unless it says otherwise
+    pub fn of_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("toml") => ConfigFormat::Toml,
+            Some(extension)
+                if extension.eq_ignore_ascii_case("yaml")
+                    || extension.eq_ignore_ascii_case("yml") =>
+            {
+                ConfigFormat::Yaml
+            }
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    pub fn parse(self, content: &str) -> Result<Config, Box<dyn std::error::Error>> {
+        Ok(match self {
+            ConfigFormat::Toml => toml::from_str(content)?,
+            ConfigFormat::Yaml => serde_yaml::from_str(content)?,
+            ConfigFormat::Json => serde_json::from_str(content)?,
+        })
+    }
+
+    pub fn render(self, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(match self {
+            ConfigFormat::Toml => toml::to_string_pretty(config)?,
+            ConfigFormat::Yaml => serde_yaml::to_string(config)?,
+            ConfigFormat::Json => serde_json::to_string_pretty(config)?,
+        })
+    }
+}
+
+/// What to do when the target file already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OnConflict {
+    #[value(skip)]
+    Prompt,
+    /// Keep both, numbering the incoming file as `name (1).ext`
+    Rename,
+    /// Replace the existing file
+    Overwrite,
+    /// Leave both files where they are
+    Skip,
+}
+
+/// How a file gets into the target directory, or out of the way with `Delete`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferMode {
+    Move,
+    Copy,
+    HardLink,
+    Symlink,
+    Delete,
+}
+
+impl TransferMode {
+    pub fn verb(self) -> &'static str {
+        match self {
+            TransferMode::Move => "move",
+            TransferMode::Copy => "copy",
+            TransferMode::HardLink => "hard link",
+            TransferMode::Symlink => "symlink",
+            TransferMode::Delete => "delete",
+        }
+    }
+
+    pub fn past_tense(self) -> &'static str {
+        match self {
+            TransferMode::Move => "moved",
+            TransferMode::Copy => "copied",
+            TransferMode::HardLink => "hard linked",
+            TransferMode::Symlink => "symlinked",
+            TransferMode::Delete => "deleted",
+        }
+    }
+}
+
+/// How the selected files are transferred
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct MoveOptions {
+    pub target_dir: PathBuf,
+    pub mode: TransferMode,
+    pub use_trash: bool,
+    pub dry_run: bool,
+    pub on_conflict: OnConflict,
+    pub open_after: bool,
+    pub remember_destination: bool,
+    pub verify: bool,
+    pub type_routing: BTreeMap<String, Vec<String>>,
+    pub date_subdir: Option<String>,
+    pub retry: RetryPolicy,
+    pub post_move_hook: Option<String>,
+    // Extract archives into the target directory instead of moving them
+    pub extract: bool,
+    // Remove an archive once it was extracted
+    pub delete_archive: bool,
+    // Leave a symlink to the moved file where it was
+    pub leave_link: bool,
+    // Directories that also get a copy, with the original only removed once all have one
+    pub fanout: Vec<PathBuf>,
+    // Leave out success messages and warnings
+    pub quiet: bool,
+    // Draw progress bars with ASCII only
+    pub ascii: bool,
+}
+
+impl MoveOptions {
+    /// Move files into `target_dir`, asking before overwriting anything
+    pub fn new(target_dir: PathBuf) -> Self {
+        MoveOptions {
+            target_dir,
+            mode: TransferMode::Move,
+            use_trash: false,
+            dry_run: false,
+            on_conflict: OnConflict::Prompt,
+            open_after: false,
+            remember_destination: false,
+            verify: false,
+            type_routing: BTreeMap::new(),
+            date_subdir: None,
+            retry: RetryPolicy {
+                retries: 0,
+                delay: Duration::from_millis(default_retry_delay_ms()),
+            },
+            post_move_hook: None,
+            extract: false,
+            delete_archive: false,
+            leave_link: false,
+            fanout: vec![],
+            quiet: false,
+            ascii: false,
+        }
+    }
+}
+
+/// A directory on another machine, reached over SFTP
+#[derive(Debug, Clone)]
+pub struct RemoteTarget {
+    /// `host` or `user@host`, as `ssh` takes it
+    pub host: String,
+    /// Empty for the remote home directory
+    pub dir: String,
+}
+
+impl fmt::Display for RemoteTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.dir)
+    }
+}
+
+/// How often a transfer failing with a transient error, as network drives
+/// occasionally do, is tried again
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Run `operation`, retrying transient errors with a delay that doubles each time.
+    /// Other errors, such as a missing file, are returned at once.
+    fn run<T>(self, what: &str, mut operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut delay = self.delay;
+        let mut attempt = 0;
+        loop {
+            match operation() {
+                Err(e) if attempt < self.retries && is_transient(&e) => {
+                    attempt += 1;
+                    debug!(
+                        "{} failed: {}, retrying in {}ms ({} of {})",
+                        what,
+                        e,
+                        delay.as_millis(),
+                        attempt,
+                        self.retries
+                    );
+                    thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+    )
+}
+
+/// Result of a single move that did not fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveOutcome {
+    Moved,
+    Skipped,
+}
+
+/// Which metadata timestamp a file's time is taken from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeSource {
+    #[default]
+    Created,
+    // Also used when the platform or filesystem does not record creation time
+    Modified,
+    Accessed,
+}
+
+impl TimeSource {
+    fn past_tense(self) -> &'static str {
+        match self {
+            TimeSource::Created => "created",
+            TimeSource::Modified => "modified",
+            TimeSource::Accessed => "accessed",
+        }
+    }
+}
+
+/// A file or directory found by the scan
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct FileInfo {
+    pub path: PathBuf,
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+    #[serde(skip)]
+    pub created_time: String,
+    pub created_timestamp: u64,
+    pub time_source: TimeSource,
+    #[serde(skip)]
+    pub time_fallback: bool,
+    // Other files collapsed into this one by `dedup_by`
+    pub duplicates: usize,
+    #[serde(skip)]
+    pub source_root: PathBuf,
+    // The name, or the path below the source directory with `show_relative_path`
+    #[serde(skip)]
+    pub display_name: String,
+    // At least `warn_age` old
+    #[serde(skip)]
+    warn: bool,
+    #[serde(skip)]
+    layout: Arc<ListLayout>,
+}
+
+/// The `display_format` of the list, and the width of each column across all files
+#[derive(Debug, Default)]
+struct ListLayout {
+    format: String,
+    icons: bool,
+    ascii: bool,
+    units: SizeUnits,
+    time_width: usize,
+    // Width of the number part of the size, which is right-aligned
+    size_width: usize,
+    unit_width: usize,
+    name_width: usize,
+    path_width: usize,
+    ext_width: usize,
+}
+
+/// The kinds of files `show_icons` tells apart
+#[derive(Debug, Clone, Copy)]
+enum FileKind {
+    Folder,
+    Image,
+    Archive,
+    Document,
+    Code,
+    Other,
+}
+
+impl FileKind {
+    fn of_path(path: &Path) -> Self {
+        let Some(ext) = path.extension() else {
+            return FileKind::Other;
+        };
+        match ext.to_string_lossy().to_lowercase().as_str() {
+            "png" | "jpg" | "jpeg" | "gif" | "bmp" | "webp" | "svg" | "heic" | "tif" | "tiff"
+            | "ico" => FileKind::Image,
+            "zip" | "tar" | "gz" | "tgz" | "bz2" | "xz" | "zst" | "7z" | "rar" | "dmg" | "iso" => {
+                FileKind::Archive
+            }
+            "pdf" | "doc" | "docx" | "odt" | "rtf" | "txt" | "md" | "xls" | "xlsx" | "ods"
+            | "csv" | "ppt" | "pptx" | "odp" | "epub" => FileKind::Document,
+            "rs" | "py" | "js" | "ts" | "c" | "h" | "cpp" | "go" | "java" | "rb" | "sh"
+            | "json" | "toml" | "yaml" | "yml" | "html" | "css" => FileKind::Code,
+            _ => FileKind::Other,
+        }
+    }
+
+    // Emoji that are two columns wide everywhere, so the names stay aligned
+    fn icon(self) -> &'static str {
+        match self {
+            FileKind::Folder => "📁",
+            FileKind::Image => "📷",
+            FileKind::Archive => "📦",
+            FileKind::Document => "📄",
+            FileKind::Code => "💻",
+            FileKind::Other => "📎",
+        }
+    }
+
+    fn ascii_icon(self) -> &'static str {
+        match self {
+            FileKind::Folder => "[/]",
+            FileKind::Image => "[i]",
+            FileKind::Archive => "[z]",
+            FileKind::Document => "[d]",
+            FileKind::Code => "[c]",
+            FileKind::Other => "[-]",
+        }
+    }
+}
+
+/// A piece of `display_format`, either literal text or a column
+#[derive(Debug, PartialEq)]
+enum FormatPart<'a> {
+    Text(&'a str),
+    Column(&'a str),
+}
+
+const DISPLAY_COLUMNS: &[&str] = &["time", "size", "name", "path", "ext"];
+
+/// Split a `display_format` into text and `{column}` placeholders
+fn parse_display_format(format: &str) -> Result<Vec<FormatPart<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = format;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(FormatPart::Text(&rest[..start]));
+        }
+        let Some(end) = rest[start..].find('}') else {
+            return Err(format!("'{}' has a '{{' without a closing '}}'", format));
+        };
+        let column = &rest[start + 1..start + end];
+        if !DISPLAY_COLUMNS.contains(&column) {
+            return Err(format!(
+                "'{{{}}}' is not one of {{time}}, {{size}}, {{name}}, {{path}} or {{ext}}",
+                column
+            ));
+        }
+        parts.push(FormatPart::Column(column));
+        rest = &rest[start + end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(FormatPart::Text(rest));
+    }
+    Ok(parts)
+}
+
+impl FileInfo {
+    /// The time column, marked with `*` when it fell back to the modification time
+    fn time_column(&self) -> String {
+        if self.time_fallback {
+            format!("{}*", self.created_time)
+        } else {
+            self.created_time.clone()
+        }
+    }
+
+    /// A glyph for the kind of file with `show_icons`, padded to the same width for every kind
+    pub fn icon(&self) -> Option<&'static str> {
+        if !self.layout.icons {
+            return None;
+        }
+        let kind = if self.is_dir {
+            FileKind::Folder
+        } else {
+            FileKind::of_path(&self.path)
+        };
+        Some(if self.layout.ascii {
+            kind.ascii_icon()
+        } else {
+            kind.icon()
+        })
+    }
+
+    fn name_column(&self) -> String {
+        if self.is_dir {
+            format!("{}/", self.display_name)
+        } else {
+            self.display_name.clone()
+        }
+    }
+
+    fn path_column(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn ext_column(&self) -> String {
+        match self.path.extension() {
+            Some(ext) if !self.is_dir => ext.to_string_lossy().to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+impl fmt::Display for FileInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let layout = &self.layout;
+        // The format was checked by validate_config
+        let parts = parse_display_format(&layout.format).unwrap_or_default();
+        for (index, part) in parts.iter().enumerate() {
+            let column = match part {
+                FormatPart::Text(text) => {
+                    f.write_str(text)?;
+                    continue;
+                }
+                FormatPart::Column(column) => *column,
+            };
+            // The last column is not padded, which would only add trailing spaces
+            let width = |width| if index + 1 == parts.len() { 0 } else { width };
+            match column {
+                "time" => {
+                    let time = pad_to_width(&self.time_column(), width(layout.time_width));
+                    if self.warn {
+                        write!(f, "{}", styled(time.yellow()))?;
+                    } else {
+                        f.write_str(&time)?;
+                    }
+                }
+                "size" => {
+                    let size = format_size(self.size, layout.units);
+                    let (number, unit) = split_size(&size);
+                    write!(
+                        f,
+                        "{:>number_width$}{}",
+                        number,
+                        pad_to_width(unit, width(layout.unit_width)),
+                        number_width = layout.size_width,
+                    )?;
+                }
+                "name" => {
+                    if let Some(icon) = self.icon() {
+                        write!(f, "{} ", icon)?;
+                    }
+                    f.write_str(&pad_to_width(&self.name_column(), width(layout.name_width)))?
+                }
+                "path" => {
+                    f.write_str(&pad_to_width(&self.path_column(), width(layout.path_width)))?
+                }
+                _ => f.write_str(&pad_to_width(&self.ext_column(), width(layout.ext_width)))?,
+            }
+        }
+        match self.duplicates {
+            0 => Ok(()),
+            1 => write!(f, " (+1 duplicate)"),
+            n => write!(f, " (+{} duplicates)", n),
+        }
+    }
+}
+
+/// Pad with spaces to a number of terminal columns. The padding of `format!` counts
+/// characters, which misaligns wide characters such as CJK or emoji.
+pub fn pad_to_width(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(text.width());
+    format!("{}{}", text, " ".repeat(padding))
+}
+
+/// Split a size from `format_size` into its number and unit, such as "1.2" and "MB"
+fn split_size(size: &str) -> (&str, &str) {
+    size.split_at(
+        size.find(|c: char| c.is_ascii_alphabetic())
+            .unwrap_or(size.len()),
+    )
+}
+
+/// A row of the selector, either a file or the header of an age group
+#[derive(Debug, Clone)]
+pub enum ListEntry {
+    Header { label: &'static str, ascii: bool },
+    File(FileInfo),
+}
+
+impl ListEntry {
+    pub fn display_name(&self) -> &str {
+        match self {
+            ListEntry::Header { label, .. } => label,
+            ListEntry::File(file) => &file.display_name,
+        }
+    }
+
+    /// The name with its icon, for the answer shown after selecting
+    pub fn label(&self) -> String {
+        if let ListEntry::File(file) = self
+            && let Some(icon) = file.icon()
+        {
+            return format!("{} {}", icon, file.display_name);
+        }
+        self.display_name().to_string()
+    }
+}
+
+impl fmt::Display for ListEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListEntry::Header { label, ascii: true } => write!(f, "-- {} --", label),
+            ListEntry::Header { label, .. } => write!(f, "── {} ──", label),
+            ListEntry::File(file) => file.fmt(f),
+        }
+    }
+}
+
+/// Wrap the files for the selector, with `group_by_age` splitting them into
+/// groups by age under a header each. Files keep the sort order within a group.
+pub fn list_entries(files: Vec<FileInfo>, config: &Config) -> Vec<ListEntry> {
+    if !config.group_by_age {
+        return files.into_iter().map(ListEntry::File).collect();
+    }
+
+    let now = chrono::Local::now();
+    let midnight = now
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|midnight| midnight.and_local_timezone(chrono::Local).earliest())
+        .map_or(0, |midnight| midnight.timestamp());
+    let now = now.timestamp();
+    let bucket = |file: &FileInfo| {
+        let timestamp = file.created_timestamp as i64;
+        if now - timestamp < 5 * 60 {
+            0
+        } else if now - timestamp < 60 * 60 {
+            1
+        } else if timestamp >= midnight {
+            2
+        } else {
+            3
+        }
+    };
+    const LABELS: [&str; 4] = ["Last 5 minutes", "Last hour", "Today", "Earlier"];
+
+    let mut groups: [Vec<FileInfo>; 4] = Default::default();
+    for file in files {
+        groups[bucket(&file)].push(file);
+    }
+    // Newest group first, unless the list is reversed
+    let mut order = [0, 1, 2, 3];
+    if config.reverse {
+        order.reverse();
+    }
+
+    let mut entries = Vec::new();
+    for index in order {
+        let group = std::mem::take(&mut groups[index]);
+        if !group.is_empty() {
+            entries.push(ListEntry::Header {
+                label: LABELS[index],
+                ascii: config.ascii,
+            });
+            entries.extend(group.into_iter().map(ListEntry::File));
+        }
+    }
+    entries
+}
+
+/// `~/.config/m`, where the config file, history and caches live
+pub fn config_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(dirs::home_dir()
+        .ok_or("Could not find home directory")?
+        .join(".config")
+        .join("m"))
+}
+
+/// Read the config file, then apply the environment variables on top. Command-line
+/// flags are applied later, so the precedence is CLI > environment > file > defaults.
+/// A newly created default config is announced unless `quiet`.
+pub fn read_config(
+    custom_path: Option<&Path>,
+    format: ConfigFormat,
+    quiet: bool,
+) -> Result<Config, Box<dyn std::error::Error>> {
+    let mut config = read_config_file(custom_path, format, quiet)?;
+
+    if apply_env_overrides(&mut config)? {
+        validate_config(&config).map_err(|problems| {
+            format!(
+                "Invalid settings from the environment:\n  - {}",
+                problems.join("\n  - ")
+            )
+        })?;
+    }
+
+    Ok(config)
+}
+
+/// Override config fields from `M_TIME_LIMIT`, `M_SOURCE_DIR` (a list separated like
+/// `PATH`) and `M_TARGET_DIR`. Returns whether any of them was set.
+fn apply_env_overrides(config: &mut Config) -> Result<bool, Box<dyn std::error::Error>> {
+    let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+    let mut applied = false;
+
+    if let Some(value) = var("M_TIME_LIMIT") {
+        let value = value.to_string_lossy();
+        config.time_limit = parse_time_limit(&value)
+            .map_err(|e| format!("Invalid M_TIME_LIMIT '{}': {}", value, e))?;
+        for source in &mut config.source_dir {
+            source.clear_time_limit();
+        }
+        applied = true;
+    }
+    if let Some(value) = var("M_SOURCE_DIR") {
+        config.source_dir = std::env::split_paths(&value)
+            .map(|path| SourceDir::Path(path.to_string_lossy().to_string()))
+            .collect();
+        applied = true;
+    }
+    if let Some(value) = var("M_TARGET_DIR") {
+        config.target_dir = value.to_string_lossy().to_string();
+        applied = true;
+    }
+
+    Ok(applied)
+}
+
+/// Read the given config file, or the first of m.toml, m.yaml and m.json in the config
+/// directory. Without any, a default config is created in `format`.
+fn read_config_file(
+    custom_path: Option<&Path>,
+    format: ConfigFormat,
+    quiet: bool,
+) -> Result<Config, Box<dyn std::error::Error>> {
+    if let Some(config_path) = custom_path {
+        // Only the default location gets a generated config
+        if !config_path.exists() {
+            return Err(format!("'{}' does not exist", config_path.display()).into());
+        }
+        return load_config(config_path);
+    }
+
+    let config_dir = config_dir()?;
+    let existing = ConfigFormat::ALL
+        .iter()
+        .map(|format| config_dir.join(format!("m.{}", format.extension())))
+        .find(|path| path.exists());
+
+    let Some(config_path) = existing else {
+        // Create default config if it doesn't exist
+        let config_path = config_dir.join(format!("m.{}", format.extension()));
+        fs::create_dir_all(&config_dir)?;
+
+        let default_config = default_config();
+
+        let content = format.render(&default_config)?;
+
+        fs::write(&config_path, content)?;
+        warning!(
+            quiet,
+            "Created default configuration file at: {}",
+            config_path.display()
+        );
+        return Ok(default_config);
+    };
+
+    load_config(&config_path)
+}
+
+/// The config written when there is no config file yet
+pub fn default_config() -> Config {
+    let home_dir = dirs::home_dir().unwrap();
+    Config {
+        source_dir: vec![SourceDir::Path(
+            home_dir.join("Downloads").to_string_lossy().to_string(),
+        )],
+        source_list_file: None,
+        time_limit: TimeLimit(20 * 60),
+        black_list: vec![],
+        black_list_ignore_case: false,
+        black_list_regex: vec![],
+        white_list: vec![],
+        skip_dirs: vec![],
+        target_dir: default_target_dir(),
+        extensions: vec![],
+        mime_allow: vec![],
+        mime_deny: vec![],
+        name_contains: None,
+        min_size: None,
+        max_size: None,
+        max_depth: None,
+        sort_by: SortBy::default(),
+        reverse: false,
+        group_dirs: GroupDirs::default(),
+        limit: 0,
+        show_icons: false,
+        page_size: None,
+        refine_threshold: None,
+        warn_age: None,
+        max_stale: None,
+        time_format: TimeFormat::default(),
+        display_format: default_display_format(),
+        follow_symlinks: false,
+        respect_ignore: false,
+        size_units: SizeUnits::default(),
+        quiet: false,
+        ascii: false,
+        color: ColorMode::default(),
+        from_contains: vec![],
+        include_dirs: false,
+        include_hidden: false,
+        time_basis: TimeSource::Created,
+        time_match: None,
+        remember_destination: false,
+        dedup_by: DedupBy::None,
+        show_relative_path: false,
+        group_by_age: false,
+        skip_incomplete: false,
+        skip_empty: false,
+        only_empty: false,
+        scan_cache: false,
+        type_routing: BTreeMap::new(),
+        date_subdir: None,
+        retry_count: 0,
+        retry_delay_ms: default_retry_delay_ms(),
+        post_move_hook: None,
+        notify: false,
+        destinations: vec![],
+        fanout: vec![],
+    }
+}
+
+fn load_config(config_path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(config_path)?;
+    let config = ConfigFormat::of_path(config_path)
+        .parse(&content)
+        .map_err(|e| format!("{}: {}", config_path.display(), e))?;
+
+    validate_config(&config).map_err(|problems| {
+        format!(
+            "{} has invalid fields:\n  - {}",
+            config_path.display(),
+            problems.join("\n  - ")
+        )
+    })?;
+
+    Ok(config)
+}
+
+/// Check field values serde cannot, collecting every problem instead of stopping at the first
+pub fn validate_config(config: &Config) -> Result<(), Vec<String>> {
+    let mut problems = Vec::new();
+
+    let sources = source_dirs(config).unwrap_or_else(|e| {
+        problems.push(format!("source_list_file: {}", e));
+        config.source_dir.clone()
+    });
+    for source in &sources {
+        if source.path() != "." && !expand_path(source.path()).is_absolute() {
+            problems.push(format!(
+                "source_dir: '{}' must be an absolute path after expanding '~' and variables, or \".\"",
+                source.path()
+            ));
+        }
+        if source.time_limit(config).seconds() != 0 {
+            continue;
+        }
+        if matches!(
+            source,
+            SourceDir::Detailed {
+                time_limit: Some(_),
+                ..
+            }
+        ) {
+            problems.push(format!(
+                "source_dir: the time_limit of 0 for '{}' includes no files, use a number of minutes or a duration such as \"2h\"",
+                source.path()
+            ));
+        }
+    }
+    if config.time_limit.seconds() == 0 {
+        problems.push(
+            "time_limit: 0 includes no files, use a number of minutes or a duration such as \"2h\""
+                .to_string(),
+        );
+    }
+    if let Some(format) = &config.date_subdir {
+        let invalid = StrftimeItems::new(format).any(|item| matches!(item, Item::Error));
+        if invalid || format.trim().is_empty() {
+            problems.push(format!(
+                "date_subdir: '{}' is not a valid date format such as \"%Y-%m-%d\"",
+                format
+            ));
+        } else if Path::new(format).is_absolute()
+            || format.split(['/', '\\']).any(|part| part == "..")
+        {
+            problems.push(format!(
+                "date_subdir: '{}' must stay inside target_dir",
+                format
+            ));
+        }
+    }
+
+    let mut routed = HashMap::new();
+    for (subdir, extensions) in &config.type_routing {
+        if subdir.trim().is_empty()
+            || Path::new(subdir).is_absolute()
+            || subdir.split(['/', '\\']).any(|part| part == "..")
+        {
+            problems.push(format!(
+                "type_routing: '{}' must be a directory inside target_dir",
+                subdir
+            ));
+        }
+        for ext in extensions {
+            let ext = ext.trim_start_matches('.').to_lowercase();
+            if let Some(other) = routed.insert(ext.clone(), subdir) {
+                problems.push(format!(
+                    "type_routing: '{}' is routed to both '{}' and '{}'",
+                    ext, other, subdir
+                ));
+            }
+        }
+    }
+
+    for (field, patterns) in [
+        ("mime_allow", &config.mime_allow),
+        ("mime_deny", &config.mime_deny),
+    ] {
+        for pattern in patterns {
+            let valid = pattern
+                .split_once('/')
+                .is_some_and(|(kind, subtype)| !kind.is_empty() && !subtype.is_empty());
+            if !valid {
+                problems.push(format!(
+                    "{}: '{}' is not a content type such as \"image/png\" or \"image/*\"",
+                    field, pattern
+                ));
+            }
+        }
+    }
+
+    if let Err(e) = parse_display_format(&config.display_format) {
+        problems.push(format!("display_format: {}", e));
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems)
+    }
+}
+
+/// `source_dir` followed by the directories in `source_list_file`, one per line.
+/// Blank lines, `#` comments and directories already in `source_dir` are skipped.
+pub fn source_dirs(config: &Config) -> Result<Vec<SourceDir>, String> {
+    let mut sources = config.source_dir.clone();
+    let Some(list_file) = &config.source_list_file else {
+        return Ok(sources);
+    };
+    let list = fs::read_to_string(expand_path(list_file))
+        .map_err(|e| format!("Failed to read '{}': {}", list_file, e))?;
+    for line in list.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if !sources.iter().any(|source| source.path() == line) {
+            sources.push(SourceDir::Path(line.to_string()));
+        }
+    }
+    Ok(sources)
+}
+
+/// The configured source directories that do not exist, which the scan skips
+pub fn missing_source_dirs(config: &Config) -> Vec<PathBuf> {
+    source_dirs(config)
+        .unwrap_or_else(|_| config.source_dir.clone())
+        .iter()
+        .map(|source| expand_path(source.path()))
+        .filter(|path| !path.exists())
+        .collect()
+}
+
+/// Scan the source directories for files within the time limit, sorted and limited
+/// as configured
+pub fn find_recent_files(
+    config: &Config,
+) -> Result<(Vec<FileInfo>, ScanStats), Box<dyn std::error::Error>> {
+    let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    let min_size = parse_size_bound(&config.min_size, "min_size", config.size_units)?.unwrap_or(0);
+    let max_size =
+        parse_size_bound(&config.max_size, "max_size", config.size_units)?.unwrap_or(u64::MAX);
+    if min_size > max_size {
+        return Err(format!(
+            "min_size ({}) is larger than max_size ({})",
+            format_size(min_size, config.size_units),
+            format_size(max_size, config.size_units)
+        )
+        .into());
+    }
+    let black_list_regex = RegexSetBuilder::new(&config.black_list_regex)
+        .case_insensitive(config.black_list_ignore_case)
+        .build()
+        .map_err(|e| format!("Invalid black_list_regex pattern: {}", e))?;
+    let white_list = PatternSet::new(&config.white_list, false)
+        .map_err(|e| format!("Invalid white_list pattern: {}", e))?;
+    let skip_dirs = PatternSet::new(&config.skip_dirs, config.black_list_ignore_case)
+        .map_err(|e| format!("Invalid skip_dirs pattern: {}", e))?;
+
+    if !config.from_contains.is_empty() && !cfg!(target_os = "macos") {
+        static WARN_FROM_CONTAINS: Once = Once::new();
+        WARN_FROM_CONTAINS.call_once(|| {
+            warning!(
+                config.quiet,
+                "{}",
+                styled("from_contains is only supported on macOS and is ignored".yellow())
+            );
+        });
+    }
+
+    // Without source directories, or with ".", the current directory is scanned
+    let mut sources = source_dirs(config).map_err(|e| format!("source_list_file: {}", e))?;
+    if sources.is_empty() {
+        sources.push(SourceDir::Path(".".to_string()));
+    }
+
+    let cache = config.scan_cache.then(|| {
+        Arc::new(ScanCache {
+            previous: read_scan_cache(),
+            current: Mutex::new(HashMap::new()),
+        })
+    });
+
+    // Each source directory gets its own filter, as it may override the global settings
+    let mut scans = Vec::new();
+    for source in &sources {
+        let source_path = expand_path(source.path());
+        if !source_path.exists() {
+            debug!(
+                "Skipping source directory '{}': does not exist",
+                source_path.display()
+            );
+            continue;
+        }
+
+        let filter = ScanFilter {
+            root: source_path.clone(),
+            quiet: config.quiet,
+            now: current_time,
+            not_before: current_time.saturating_sub(source.time_limit(config).seconds()),
+            black_list: PatternSet::new(source.black_list(config), config.black_list_ignore_case)
+                .map_err(|e| format!("Invalid black_list pattern: {}", e))?,
+            black_list_regex: black_list_regex.clone(),
+            white_list: white_list.clone(),
+            skip_dirs: skip_dirs.clone(),
+            extensions: source.extensions(config).to_vec(),
+            name_contains: config
+                .name_contains
+                .as_ref()
+                .map(|name| name.to_lowercase()),
+            min_size,
+            max_size,
+            visited: Mutex::new(HashSet::new()),
+            dirs_walked: AtomicUsize::new(0),
+            cache: cache.clone(),
+            newest: keeps_newest(config).then(|| Mutex::new(BinaryHeap::new())),
+        };
+        debug!(
+            "Looking in '{}' for files since {}",
+            source_path.display(),
+            format_time(filter.not_before, filter.now, TimeFormat::Iso)
+        );
+        scans.push((source_path, filter));
+    }
+
+    // Recursively scan source directories in parallel
+    let nested = scans
+        .par_iter()
+        .map(|(source_path, filter)| {
+            let mut files = Vec::new();
+            scan_directory(source_path, config, &mut files, filter, 0, &[])?;
+            Ok(files)
+        })
+        .collect::<Result<Vec<_>, ScanError>>()
+        .map_err(|e| -> Box<dyn std::error::Error> { e })?;
+    if let Some(cache) = cache
+        && let Err(e) = write_scan_cache(&cache.current.lock().unwrap())
+    {
+        warning!(
+            config.quiet,
+            "{}",
+            styled(format!("Failed to save the scan cache: {}", e).yellow())
+        );
+    }
+    let mut files = dedup_files(nested.into_iter().flatten().collect(), config.dedup_by);
+
+    let stats = ScanStats {
+        dirs_walked: scans
+            .iter()
+            .map(|(_, filter)| filter.dirs_walked.load(Ordering::Relaxed))
+            .sum(),
+        files_matched: files.len(),
+        total_size: files.iter().map(|f| f.size).sum(),
+        time_limit: config.time_limit,
+        size_units: config.size_units,
+    };
+
+    match config.sort_by {
+        SortBy::Time => files.sort_by_key(|f| std::cmp::Reverse(f.created_timestamp)),
+        SortBy::Size => files.sort_by_key(|f| std::cmp::Reverse(f.size)),
+        SortBy::Name => files.sort_by_cached_key(|f| f.name.to_lowercase()),
+    }
+    if config.reverse {
+        files.reverse();
+    }
+    // The sort is stable, so both groups keep the order above
+    match config.group_dirs {
+        GroupDirs::Mixed => {}
+        GroupDirs::First => files.sort_by_key(|f| !f.is_dir),
+        GroupDirs::Last => files.sort_by_key(|f| f.is_dir),
+    }
+    if config.limit > 0 {
+        files.truncate(config.limit);
+    }
+
+    for file in &mut files {
+        file.warn = config.warn_age.is_some_and(|age| {
+            current_time.saturating_sub(file.created_timestamp) >= age.seconds()
+        });
+        file.display_name = match file.path.strip_prefix(&file.source_root) {
+            Ok(relative) if config.show_relative_path => relative.to_string_lossy().to_string(),
+            _ => file.name.clone(),
+        };
+    }
+
+    // Calculate column widths for the entire list, for the columns that are shown
+    let shown = |column: &str| config.display_format.contains(&format!("{{{}}}", column));
+    let column_width = |column: &str, width: fn(&FileInfo) -> usize| {
+        if shown(column) {
+            files.iter().map(width).max().unwrap_or(0)
+        } else {
+            0
+        }
+    };
+
+    // Sizes are aligned on the end of the number, with the units in a column after it
+    let (size_width, unit_width) = files
+        .iter()
+        .map(|f| {
+            let size = format_size(f.size, config.size_units);
+            let (number, unit) = split_size(&size);
+            (number.len(), unit.len())
+        })
+        .fold((0, 0), |(number_max, unit_max), (number, unit)| {
+            (number_max.max(number), unit_max.max(unit))
+        });
+
+    let layout = Arc::new(ListLayout {
+        format: config.display_format.clone(),
+        icons: config.show_icons,
+        ascii: config.ascii,
+        units: config.size_units,
+        time_width: column_width("time", |f| f.time_column().width()),
+        size_width,
+        unit_width,
+        name_width: column_width("name", |f| f.name_column().width()),
+        path_width: column_width("path", |f| f.path_column().width()),
+        ext_width: column_width("ext", |f| f.ext_column().width()),
+    });
+    for file in &mut files {
+        file.layout = layout.clone();
+    }
+
+    Ok((files, stats))
+}
+
+fn scan_directory(
+    dir_path: &Path,
+    config: &Config,
+    files: &mut Vec<FileInfo>,
+    filter: &ScanFilter,
+    depth: u32,
+    ignores: &[Arc<Gitignore>],
+) -> Result<(), ScanError> {
+    let (subdirs, ignores) = scan_entries(dir_path, config, files, filter, depth, ignores)?;
+
+    // Recursively scan subdirectories
+    files.extend(scan_in_parallel(
+        &subdirs,
+        config,
+        filter,
+        depth + 1,
+        &ignores,
+    )?);
+
+    Ok(())
+}
+
+/// Scan the entries of one directory into `files`. Returns the subdirectories to
+/// scan next, along with the ignore files that apply to them.
+fn scan_entries(
+    dir_path: &Path,
+    config: &Config,
+    files: &mut Vec<FileInfo>,
+    filter: &ScanFilter,
+    depth: u32,
+    ignores: &[Arc<Gitignore>],
+) -> Result<(Vec<PathBuf>, Vec<Arc<Gitignore>>), ScanError> {
+    if config.follow_symlinks {
+        // Each directory is scanned once, even when reached through several links
+        let canonical = match fs::canonicalize(dir_path) {
+            Ok(canonical) => canonical,
+            Err(e) => {
+                warn_unreadable(dir_path, &e, config.quiet);
+                return Ok(Default::default());
+            }
+        };
+        if !filter.visited.lock().unwrap().insert(canonical) {
+            return Ok(Default::default());
+        }
+    }
+
+    // An unreadable source directory is an error, unreadable subdirectories are skipped
+    let entries = match list_directory(dir_path, filter) {
+        Ok(entries) => entries,
+        Err(e) if depth == 0 => return Err(e.into()),
+        Err(e) => {
+            warn_unreadable(dir_path, &e, config.quiet);
+            return Ok(Default::default());
+        }
+    };
+    filter.dirs_walked.fetch_add(1, Ordering::Relaxed);
+    debug!("Scanning '{}'", dir_path.display());
+    let mut subdirs = Vec::new();
+
+    // A .gitignore applies to this directory and everything below it
+    let mut nested_ignores;
+    let mut ignores = ignores;
+    let gitignore_path = dir_path.join(".gitignore");
+    if config.respect_ignore && gitignore_path.is_file() {
+        let (gitignore, error) = Gitignore::new(&gitignore_path);
+        if let Some(e) = error {
+            warning!(
+                config.quiet,
+                "{}",
+                styled(
+                    format!(
+                        "Ignoring invalid lines in '{}': {}",
+                        gitignore_path.display(),
+                        e
+                    )
+                    .yellow()
+                )
+            );
+        }
+        nested_ignores = ignores.to_vec();
+        nested_ignores.push(Arc::new(gitignore));
+        ignores = &nested_ignores;
+    }
+
+    for entry in entries {
+        // Links are resolved on every scan, as their target can change without the
+        // directory changing
+        let metadata = if entry.is_symlink {
+            if !config.follow_symlinks {
+                debug!(
+                    "Skipping '{}': a link, and follow_symlinks is off",
+                    entry.path.display()
+                );
+                continue;
+            }
+            match fs::metadata(&entry.path) {
+                Ok(metadata) => EntryInfo::new(entry.path.clone(), &metadata),
+                Err(_) => {
+                    debug!("Skipping '{}': a broken link", entry.path.display());
+                    continue;
+                }
+            }
+        } else {
+            entry.clone()
+        };
+        let file_name_str = entry.name;
+
+        if is_git_ignored(ignores, &entry.path, metadata.is_dir) {
+            debug!("Skipping '{}': ignored by .gitignore", entry.path.display());
+            continue;
+        }
+
+        // Skip if the path or file name matches any blacklisted pattern
+        if filter.black_list.is_match(&file_name_str, &entry.path)
+            || filter.black_list_regex.is_match(&file_name_str)
+        {
+            debug!("Skipping '{}': on the black_list", entry.path.display());
+            continue;
+        }
+
+        // Skip hidden files and directories
+        if !config.include_hidden && file_name_str.starts_with('.') {
+            debug!("Skipping '{}': hidden", entry.path.display());
+            continue;
+        }
+
+        if metadata.is_file {
+            // An empty whitelist includes everything
+            if !config.white_list.is_empty()
+                && !filter.white_list.is_match(&file_name_str, &entry.path)
+            {
+                debug!("Skipping '{}': not on the white_list", entry.path.display());
+                continue;
+            }
+
+            if !matches_extension(&entry.path, &filter.extensions) {
+                debug!(
+                    "Skipping '{}': not one of the extensions",
+                    entry.path.display()
+                );
+                continue;
+            }
+
+            if !matches_name(&file_name_str, filter) {
+                debug!(
+                    "Skipping '{}': name does not contain name_contains",
+                    entry.path.display()
+                );
+                continue;
+            }
+
+            let size = metadata.len;
+            if size < filter.min_size || size > filter.max_size {
+                debug!(
+                    "Skipping '{}': {} is outside min_size and max_size",
+                    entry.path.display(),
+                    format_size(size, config.size_units)
+                );
+                continue;
+            }
+            if config.skip_empty && size == 0 {
+                debug!("Skipping '{}': empty", entry.path.display());
+                continue;
+            }
+            if config.only_empty && size > 0 {
+                debug!("Skipping '{}': not empty", entry.path.display());
+                continue;
+            }
+
+            if !config.from_contains.is_empty()
+                && !matches_origin(&entry.path, &config.from_contains)
+            {
+                debug!(
+                    "Skipping '{}': not downloaded from from_contains",
+                    entry.path.display()
+                );
+                continue;
+            }
+
+            // Check if file was created within the time limit
+            let (created_time, time_source) = match matched_time(&metadata, config) {
+                Ok(time) => time,
+                Err(e) => {
+                    warn_unreadable(&entry.path, &*e, config.quiet);
+                    continue;
+                }
+            };
+
+            if created_time >= filter.not_before {
+                if below_cutoff(created_time, config, filter) {
+                    trace!(
+                        "Skipping '{}': older than the {} newest files found so far",
+                        entry.path.display(),
+                        config.limit
+                    );
+                    continue;
+                }
+                if is_stale(&metadata, config, filter) {
+                    debug!(
+                        "Skipping '{}': not modified within max_stale",
+                        entry.path.display()
+                    );
+                    continue;
+                }
+                // Last, so only the files that pass everything else are read
+                if !config.mime_allow.is_empty() || !config.mime_deny.is_empty() {
+                    let mime = content_type(&entry.path);
+                    if !matches_mime(mime, config) {
+                        debug!(
+                            "Skipping '{}': content type {} is not allowed by mime_allow and mime_deny",
+                            entry.path.display(),
+                            mime.unwrap_or("unknown")
+                        );
+                        continue;
+                    }
+                }
+                if config.skip_incomplete && is_incomplete(&entry.path, &file_name_str) {
+                    debug!("Skipping '{}': still being written", entry.path.display());
+                    continue;
+                }
+
+                let file_path = entry.path.clone();
+                let file_name = file_name_str;
+
+                let time_str = format_entry_time(created_time, time_source, filter, config);
+
+                files.push(FileInfo {
+                    path: file_path,
+                    name: file_name,
+                    size,
+                    is_dir: false,
+                    created_time: time_str,
+                    created_timestamp: created_time,
+                    time_source,
+                    time_fallback: config.time_match.is_none() && time_source != config.time_basis,
+                    duplicates: 0,
+                    source_root: filter.root.clone(),
+                    display_name: String::new(), // Will be updated later
+                    warn: false,                 // Will be updated later
+                    layout: Arc::default(),      // Will be updated later
+                });
+                record_newest(created_time, config, filter);
+                trace!("Found '{}'", entry.path.display());
+            } else {
+                debug!(
+                    "Skipping '{}': {} {}, before the time limit",
+                    entry.path.display(),
+                    time_source.past_tense(),
+                    format_time(created_time, filter.now, TimeFormat::Iso)
+                );
+            }
+        } else if metadata.is_dir {
+            if filter.skip_dirs.is_match(&file_name_str, &entry.path) {
+                debug!(
+                    "Skipping directory '{}': on skip_dirs",
+                    entry.path.display()
+                );
+                continue;
+            }
+
+            // A recent directory is offered as a whole instead of being descended into
+            if config.include_dirs {
+                let (created_time, time_source) = match matched_time(&metadata, config) {
+                    Ok(time) => time,
+                    Err(e) => {
+                        warn_unreadable(&entry.path, &*e, config.quiet);
+                        continue;
+                    }
+                };
+                if created_time >= filter.not_before {
+                    if below_cutoff(created_time, config, filter) {
+                        trace!(
+                            "Skipping directory '{}': older than the {} newest files found so far",
+                            entry.path.display(),
+                            config.limit
+                        );
+                        continue;
+                    }
+                    if is_stale(&metadata, config, filter) {
+                        debug!(
+                            "Skipping directory '{}': not modified within max_stale",
+                            entry.path.display()
+                        );
+                        continue;
+                    }
+                    if !matches_name(&file_name_str, filter) {
+                        debug!(
+                            "Skipping directory '{}': name does not contain name_contains",
+                            entry.path.display()
+                        );
+                        continue;
+                    }
+                    let size = dir_size(&entry.path);
+                    if size >= filter.min_size && size <= filter.max_size {
+                        files.push(FileInfo {
+                            path: entry.path.clone(),
+                            name: file_name_str,
+                            size,
+                            is_dir: true,
+                            created_time: format_entry_time(
+                                created_time,
+                                time_source,
+                                filter,
+                                config,
+                            ),
+                            created_timestamp: created_time,
+                            time_source,
+                            time_fallback: config.time_match.is_none()
+                                && time_source != config.time_basis,
+                            duplicates: 0,
+                            source_root: filter.root.clone(),
+                            display_name: String::new(),
+                            warn: false,
+                            layout: Arc::default(),
+                        });
+                        record_newest(created_time, config, filter);
+                    }
+                    continue;
+                }
+            }
+
+            if config.max_depth.is_none_or(|max| depth < max) {
+                subdirs.push(entry.path.clone());
+            } else {
+                debug!(
+                    "Skipping directory '{}': below max_depth",
+                    entry.path.display()
+                );
+            }
+        }
+    }
+
+    Ok((subdirs, ignores.to_vec()))
+}
+
+/// The configured timestamp in seconds, falling back to the modification time
+/// where creation or access time is unavailable
+fn entry_time(metadata: &EntryInfo, basis: TimeSource) -> Result<(u64, TimeSource), ScanError> {
+    let time = match basis {
+        TimeSource::Created => metadata.created,
+        TimeSource::Modified => metadata.modified,
+        TimeSource::Accessed => metadata.accessed,
+    };
+    let (time, time_source) = match time {
+        Some(time) => (time, basis),
+        None => (
+            metadata
+                .modified
+                .ok_or("modification time is unavailable")?,
+            TimeSource::Modified,
+        ),
+    };
+    Ok((time.duration_since(UNIX_EPOCH)?.as_secs(), time_source))
+}
+
+/// Whether a file looks like it is still being written: it has the suffix of an
+/// unfinished download, was modified within the last second, or was modified
+/// within the last few seconds and changes while it is looked at twice
+fn is_incomplete(path: &Path, name: &str) -> bool {
+    // Only files this recent are looked at twice, which costs a short wait each
+    const SETTLE_WINDOW: Duration = Duration::from_secs(5);
+    const SETTLE_CHECK: Duration = Duration::from_millis(100);
+
+    let lowercase = name.to_lowercase();
+    if INCOMPLETE_SUFFIXES
+        .iter()
+        .any(|suffix| lowercase.ends_with(suffix))
+    {
+        return true;
+    }
+
+    let Ok(first) = fs::metadata(path) else {
+        return false;
+    };
+    let age = first
+        .modified()
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+    match age {
+        Some(age) if age < Duration::from_secs(1) => true,
+        Some(age) if age < SETTLE_WINDOW => {
+            // A download that stalls for a moment is only caught by seeing it grow
+            thread::sleep(SETTLE_CHECK);
+            fs::metadata(path).is_ok_and(|second| {
+                second.len() != first.len() || second.modified().ok() != first.modified().ok()
+            })
+        }
+        _ => false,
+    }
+}
+
+/// The time checked against the time limit. With `time_match` that is the newest
+/// (`any`) or the oldest (`all`) of the creation and modification times, so the
+/// file counts as recent when any or all of them are.
+fn matched_time(metadata: &EntryInfo, config: &Config) -> Result<(u64, TimeSource), ScanError> {
+    let Some(time_match) = config.time_match else {
+        return entry_time(metadata, config.time_basis);
+    };
+    // On a tie the creation time is shown
+    let times = [
+        (metadata.created, TimeSource::Created),
+        (metadata.modified, TimeSource::Modified),
+    ]
+    .into_iter()
+    .filter_map(|(time, source)| Some((time?.duration_since(UNIX_EPOCH).ok()?.as_secs(), source)));
+    let time = match time_match {
+        TimeMatch::Any => times.rev().max_by_key(|(time, _)| *time),
+        TimeMatch::All => times.min_by_key(|(time, _)| *time),
+    };
+    Ok(time.ok_or("no creation or modification time")?)
+}
+
+/// The time column, which with `time_match` names the timestamp that was matched
+fn format_entry_time(
+    time: u64,
+    source: TimeSource,
+    filter: &ScanFilter,
+    config: &Config,
+) -> String {
+    let formatted = format_time(time, filter.now, config.time_format);
+    match config.time_match {
+        Some(_) => format!("{} {}", formatted, source.past_tense()),
+        None => formatted,
+    }
+}
+
+/// The entries of a directory, from the scan cache when the directory has not been
+/// modified since it was cached. Entries that cannot be read are warned about and
+/// left out, and keep the listing out of the cache.
+fn list_directory(dir_path: &Path, filter: &ScanFilter) -> io::Result<Vec<EntryInfo>> {
+    let Some(cache) = &filter.cache else {
+        return read_directory(dir_path, filter.quiet).map(|(entries, _)| entries);
+    };
+
+    let key = std::path::absolute(dir_path)?;
+    let modified = fs::metadata(dir_path)?.modified()?;
+    // Every entry is looked at again, only reading the directory is saved. If one has
+    // gone missing the listing is read anew.
+    if let Some(cached) = cache.previous.get(&key)
+        && cached.modified == modified
+        && let Ok(entries) = cached
+            .names
+            .iter()
+            .map(|name| {
+                let path = dir_path.join(name);
+                fs::symlink_metadata(&path).map(|metadata| EntryInfo::new(path, &metadata))
+            })
+            .collect::<io::Result<Vec<_>>>()
+    {
+        cache.current.lock().unwrap().insert(key, cached.clone());
+        trace!("Using the cached listing of '{}'", dir_path.display());
+        return Ok(entries);
+    }
+
+    let (entries, complete) = read_directory(dir_path, filter.quiet)?;
+    // A directory changed within the last seconds may change again without its time
+    // changing, as some file systems only keep whole seconds
+    let settled = SystemTime::now()
+        .duration_since(modified)
+        .is_ok_and(|age| age > Duration::from_secs(2));
+    if complete && settled {
+        let cached = CachedDir {
+            modified,
+            names: entries.iter().map(|entry| entry.name.clone()).collect(),
+        };
+        cache.current.lock().unwrap().insert(key, cached);
+    }
+    Ok(entries)
+}
+
+/// Read a directory's entries, and whether all of them could be read and have
+/// names that survive the round trip through the scan cache
+fn read_directory(dir_path: &Path, quiet: bool) -> io::Result<(Vec<EntryInfo>, bool)> {
+    let mut entries = Vec::new();
+    let mut complete = true;
+    for entry in fs::read_dir(dir_path)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn_unreadable(dir_path, &e, quiet);
+                complete = false;
+                continue;
+            }
+        };
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn_unreadable(&entry.path(), &e, quiet);
+                complete = false;
+                continue;
+            }
+        };
+        complete &= entry.file_name().to_str().is_some();
+        entries.push(EntryInfo::new(entry.path(), &metadata));
+    }
+    Ok((entries, complete))
+}
+
+/// Total size of the files in a directory tree, without following symlinks.
+/// Unreadable parts are left out of the total.
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            Ok(file_type) if file_type.is_file() => entry.metadata().map_or(0, |m| m.len()),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Warn about a file or directory the scan has to leave out, such as one
+/// without read permission, and carry on with the rest
+fn warn_unreadable(path: &Path, error: &dyn fmt::Display, quiet: bool) {
+    warning!(
+        quiet,
+        "{}",
+        styled(format!("Skipping '{}': {}", path.display(), error).yellow())
+    );
+}
+
+/// Collapse duplicates into the newest file of each group, counting the others.
+/// Content comparison only hashes files that share their size with another one.
+fn dedup_files(files: Vec<FileInfo>, dedup_by: DedupBy) -> Vec<FileInfo> {
+    let keys: Vec<Option<String>> = match dedup_by {
+        DedupBy::None => return files,
+        DedupBy::Name => files.iter().map(|f| Some(f.name.clone())).collect(),
+        DedupBy::Content => {
+            let mut sizes: HashMap<u64, usize> = HashMap::new();
+            for file in files.iter().filter(|f| !f.is_dir) {
+                *sizes.entry(file.size).or_default() += 1;
+            }
+            files
+                .par_iter()
+                .map(|f| {
+                    // Unreadable files stay in the list as they are
+                    (!f.is_dir && sizes[&f.size] > 1)
+                        .then(|| hash_file(&f.path).ok())
+                        .flatten()
+                        .map(|hash| format!("{}:{}", f.size, hash))
+                })
+                .collect()
+        }
+    };
+
+    let mut kept: Vec<FileInfo> = Vec::new();
+    let mut index_by_key: HashMap<String, usize> = HashMap::new();
+    for (mut file, key) in files.into_iter().zip(keys) {
+        let Some(key) = key else {
+            kept.push(file);
+            continue;
+        };
+        match index_by_key.get(&key) {
+            Some(&index) => {
+                let existing = &mut kept[index];
+                if file.created_timestamp > existing.created_timestamp {
+                    file.duplicates = existing.duplicates;
+                    *existing = file;
+                }
+                existing.duplicates += 1;
+            }
+            None => {
+                index_by_key.insert(key, kept.len());
+                kept.push(file);
+            }
+        }
+    }
+    kept
+}
+
+/// SHA-256 of a file's contents, read in chunks so large files are not loaded at once
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut reader = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Check the stack of .gitignore files, where the deepest one with an opinion wins
+fn is_git_ignored(ignores: &[Arc<Gitignore>], path: &Path, is_dir: bool) -> bool {
+    ignores
+        .iter()
+        .rev()
+        .map(|gitignore| gitignore.matched(path, is_dir))
+        .find(|matched| !matched.is_none())
+        .is_some_and(|matched| matched.is_ignore())
+}
+
+/// Scan each directory on the rayon thread pool, concatenating the results
+fn scan_in_parallel(
+    dirs: &[PathBuf],
+    config: &Config,
+    filter: &ScanFilter,
+    depth: u32,
+    ignores: &[Arc<Gitignore>],
+) -> Result<Vec<FileInfo>, ScanError> {
+    let nested = dirs
+        .par_iter()
+        .map(|dir| {
+            let mut files = Vec::new();
+            scan_directory(dir, config, &mut files, filter, depth, ignores)?;
+            Ok(files)
+        })
+        .collect::<Result<Vec<_>, ScanError>>()?;
+
+    Ok(nested.into_iter().flatten().collect())
+}
+
+/// Check where a file was downloaded from, as recorded by macOS in the
+/// `kMDItemWhereFroms` extended attribute, against the configured substrings
+#[cfg(target_os = "macos")]
+fn matches_origin(path: &Path, from_contains: &[String]) -> bool {
+    let Ok(Some(raw)) = xattr::get(path, "com.apple.metadata:kMDItemWhereFroms") else {
+        return false;
+    };
+    // The attribute holds a binary plist with a list of URLs
+    let Ok(origins) = plist::from_bytes::<Vec<String>>(&raw) else {
+        return false;
+    };
+
+    origins.iter().any(|origin| {
+        from_contains
+            .iter()
+            .any(|wanted| origin.contains(wanted.as_str()))
+    })
+}
+
+/// Other platforms have no origin metadata, so the filter is a no-op
+#[cfg(not(target_os = "macos"))]
+fn matches_origin(_path: &Path, _from_contains: &[String]) -> bool {
+    true
+}
+
+/// Name patterns from the config, compiled once per scan.
+///
+/// Patterns containing glob metacharacters (`*?[{`) are matched as globs, against the
+/// full path when they contain a `/` and against the file name otherwise. Anything else
+/// keeps the original substring match on the file name.
+#[derive(Clone)]
+struct PatternSet {
+    ignore_case: bool,
+    substrings: Vec<String>,
+    name_globs: GlobSet,
+    path_globs: GlobSet,
+}
+
+impl PatternSet {
+    fn new(patterns: &[String], ignore_case: bool) -> Result<Self, globset::Error> {
+        let mut substrings = Vec::new();
+        let mut name_globs = GlobSetBuilder::new();
+        let mut path_globs = GlobSetBuilder::new();
+
+        for pattern in patterns {
+            if !pattern.contains(['*', '?', '[', '{']) {
+                substrings.push(if ignore_case {
+                    pattern.to_lowercase()
+                } else {
+                    pattern.clone()
+                });
+                continue;
+            }
+
+            let glob = GlobBuilder::new(pattern)
+                .case_insensitive(ignore_case)
+                .build()?;
+            if pattern.contains('/') {
+                path_globs.add(glob);
+            } else {
+                name_globs.add(glob);
+            }
+        }
+
+        Ok(PatternSet {
+            ignore_case,
+            substrings,
+            name_globs: name_globs.build()?,
+            path_globs: path_globs.build()?,
+        })
+    }
+
+    fn is_match(&self, name: &str, path: &Path) -> bool {
+        let name_lower;
+        let name = if self.ignore_case {
+            name_lower = name.to_lowercase();
+            &name_lower
+        } else {
+            name
+        };
+
+        self.substrings.iter().any(|s| name.contains(s.as_str()))
+            || self.name_globs.is_match(name)
+            || self.path_globs.is_match(path)
+    }
+}
+
+/// Last modified longer ago than `max_stale`, such as a file restored from a backup
+/// with a new creation time. Without a modification time nothing is stale.
+fn is_stale(metadata: &EntryInfo, config: &Config, filter: &ScanFilter) -> bool {
+    let (Some(max_stale), Some(modified)) = (config.max_stale, metadata.modified) else {
+        return false;
+    };
+    modified
+        .duration_since(UNIX_EPOCH)
+        .is_ok_and(|modified| modified.as_secs() < filter.now.saturating_sub(max_stale.seconds()))
+}
+
+/// Whether the list ends up as the newest `limit` files of those found, so an
+/// older file can be dropped during the scan. Collapsing duplicates and grouping
+/// directories change which files make the cut, so they keep every file.
+fn keeps_newest(config: &Config) -> bool {
+    config.limit > 0
+        && matches!(config.sort_by, SortBy::Time)
+        && !config.reverse
+        && matches!(config.dedup_by, DedupBy::None)
+        && config.group_dirs == GroupDirs::Mixed
+}
+
+/// Whether `limit` files newer than `time` have already been found in this source
+/// directory, in which case a file of that time cannot make the list
+fn below_cutoff(time: u64, config: &Config, filter: &ScanFilter) -> bool {
+    filter.newest.as_ref().is_some_and(|newest| {
+        let newest = newest.lock().unwrap();
+        newest.len() >= config.limit && newest.peek().is_some_and(|Reverse(cutoff)| time < *cutoff)
+    })
+}
+
+fn record_newest(time: u64, config: &Config, filter: &ScanFilter) {
+    if let Some(newest) = &filter.newest {
+        let mut newest = newest.lock().unwrap();
+        newest.push(Reverse(time));
+        if newest.len() > config.limit {
+            newest.pop();
+        }
+    }
+}
+
+/// Check the name against `name_contains`, ignoring case
+fn matches_name(name: &str, filter: &ScanFilter) -> bool {
+    filter
+        .name_contains
+        .as_ref()
+        .is_none_or(|needle| name.to_lowercase().contains(needle.as_str()))
+}
+
+/// Check the file extension against the filter, an empty filter matches everything
+fn matches_extension(path: &Path, extensions: &[String]) -> bool {
+    if extensions.is_empty() {
+        return true;
+    }
+
+    let Some(ext) = path.extension() else {
+        return false;
+    };
+    let ext = ext.to_string_lossy();
+    extensions
+        .iter()
+        .any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(&ext))
+}
+
+/// Check a content type against `mime_allow` and `mime_deny`. An unknown type only
+/// passes when nothing is allowed explicitly.
+fn matches_mime(mime: Option<&str>, config: &Config) -> bool {
+    let matches = |pattern: &String| {
+        mime.is_some_and(|mime| match pattern.strip_suffix("/*") {
+            Some(kind) => mime
+                .split_once('/')
+                .is_some_and(|(mime_kind, _)| mime_kind.eq_ignore_ascii_case(kind)),
+            None => mime.eq_ignore_ascii_case(pattern),
+        })
+    };
+    (config.mime_allow.is_empty() || config.mime_allow.iter().any(matches))
+        && !config.mime_deny.iter().any(matches)
+}
+
+/// The content type from the file's magic bytes, or from its extension when they
+/// are not recognized
+fn content_type(path: &Path) -> Option<&'static str> {
+    let mut head = Vec::with_capacity(512);
+    // An unreadable file has no content to go by
+    let _ = fs::File::open(path).and_then(|file| file.take(512).read_to_end(&mut head));
+    sniff_mime(&head).or_else(|| mime_of_extension(path))
+}
+
+/// The content type of a file by its first bytes. Signatures shorter than four
+/// bytes could start ordinary text or data, so those also check the bytes after
+/// them, as does "MZ", which is only a Windows executable with a PE header.
+fn sniff_mime(head: &[u8]) -> Option<&'static str> {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"II*\0", "image/tiff"),
+        (b"MM\0*", "image/tiff"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"Rar!\x1a\x07", "application/vnd.rar"),
+        (b"\xfd7zXZ\0", "application/x-xz"),
+        (b"\x28\xb5\x2f\xfd", "application/zstd"),
+        (b"SQLite format 3\0", "application/vnd.sqlite3"),
+        (b"\x7fELF", "application/x-executable"),
+        (b"OggS", "audio/ogg"),
+        (b"fLaC", "audio/flac"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+    ];
+    if let Some((_, mime)) = MAGIC.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(mime);
+    }
+
+    let at = |offset: usize, bytes: &[u8]| head.get(offset..offset + bytes.len()) == Some(bytes);
+    let byte = |offset: usize| head.get(offset).copied();
+    let u16_at = |offset: usize| {
+        Some(u16::from_le_bytes(
+            head.get(offset..offset + 2)?.try_into().ok()?,
+        ))
+    };
+    let u32_at = |offset: usize| {
+        Some(u32::from_le_bytes(
+            head.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+
+    // The start of image marker is followed by another marker
+    if at(0, b"\xff\xd8\xff") && byte(3).is_some_and(|marker| marker >= 0xc0) {
+        return Some("image/jpeg");
+    }
+    // Deflate is the only compression method, and the top three flag bits are reserved
+    if at(0, b"\x1f\x8b\x08") && byte(3).is_some_and(|flags| flags & 0xe0 == 0) {
+        return Some("application/gzip");
+    }
+    // The block size, then the first block or the end of an empty stream
+    if at(0, b"BZh")
+        && byte(3).is_some_and(|size| (b'1'..=b'9').contains(&size))
+        && (at(4, b"1AY&SY") || at(4, b"\x17\x72\x45\x38\x50\x90"))
+    {
+        return Some("application/x-bzip2");
+    }
+    // Tag versions 2.2 to 2.4, with a size whose bytes leave out the top bit
+    if at(0, b"ID3")
+        && byte(3).is_some_and(|version| (2..=4).contains(&version))
+        && head
+            .get(6..10)
+            .is_some_and(|size| size.iter().all(|&b| b < 0x80))
+    {
+        return Some("audio/mpeg");
+    }
+    // At least one image, whose directory entry has a zero reserved byte and 0 or 1 planes
+    if at(0, b"\0\0\x01\0")
+        && u16_at(4).is_some_and(|count| count > 0)
+        && byte(9) == Some(0)
+        && u16_at(10).is_some_and(|planes| planes <= 1)
+    {
+        return Some("image/x-icon");
+    }
+    // The DOS header points at the PE header
+    if at(0, b"MZ") && u32_at(0x3c).is_some_and(|offset| at(offset as usize, b"PE\0\0")) {
+        return Some("application/vnd.microsoft.portable-executable");
+    }
+
+    if at(0, b"RIFF") {
+        return match head.get(8..12)? {
+            b"WEBP" => Some("image/webp"),
+            b"WAVE" => Some("audio/wav"),
+            b"AVI " => Some("video/x-msvideo"),
+            _ => None,
+        };
+    }
+    // ISO media files name their brand after "ftyp"
+    if at(4, b"ftyp") {
+        return Some(match head.get(8..12)? {
+            b"heic" | b"heix" | b"mif1" => "image/heic",
+            b"avif" => "image/avif",
+            b"M4A " => "audio/mp4",
+            b"qt  " => "video/quicktime",
+            _ => "video/mp4",
+        });
+    }
+    if at(257, b"ustar") {
+        return Some("application/x-tar");
+    }
+    None
+}
+
+/// Describe a file's contents: the first lines of text files, the dimensions of
+/// common image formats, or a hexdump of the first bytes of anything else
+pub fn preview_file(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    const PREVIEW_LINES: usize = 10;
+    const HEXDUMP_BYTES: usize = 64;
+
+    if path.is_dir() {
+        let mut names: Vec<String> = fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.file_name().to_string_lossy().to_string()))
+            .collect::<io::Result<_>>()?;
+        if names.is_empty() {
+            return Ok("(empty directory)".to_string());
+        }
+        names.sort();
+        let mut lines = vec![format!("Directory with {} entries", names.len())];
+        lines.extend(names.into_iter().take(PREVIEW_LINES));
+        return Ok(lines.join("\n"));
+    }
+
+    let mut header = Vec::new();
+    fs::File::open(path)?
+        .take(64 * 1024)
+        .read_to_end(&mut header)?;
+
+    if let Some((format, width, height)) = image_dimensions(&header) {
+        return Ok(format!("{} image, {}x{} pixels", format, width, height));
+    }
+
+    // Treat NUL-free UTF-8 as text, allowing a multibyte character cut off at the end
+    let text = match std::str::from_utf8(&header) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&header[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    };
+    if let Some(text) = text.filter(|text| !text.contains('\0')) {
+        if text.is_empty() {
+            return Ok("(empty file)".to_string());
+        }
+        let lines: Vec<&str> = text.lines().take(PREVIEW_LINES).collect();
+        return Ok(lines.join("\n"));
+    }
+
+    let lines: Vec<String> = header[..header.len().min(HEXDUMP_BYTES)]
+        .chunks(16)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                .collect();
+            format!("{:08x}  {:<47}  {}", row * 16, hex.join(" "), ascii)
+        })
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+/// Read the pixel dimensions from a PNG, GIF, BMP or JPEG header
+fn image_dimensions(header: &[u8]) -> Option<(&'static str, u32, u32)> {
+    let be16 = |i: usize| Some(u16::from_be_bytes(header.get(i..i + 2)?.try_into().ok()?) as u32);
+    let le16 = |i: usize| Some(u16::from_le_bytes(header.get(i..i + 2)?.try_into().ok()?) as u32);
+    let be32 = |i: usize| Some(u32::from_be_bytes(header.get(i..i + 4)?.try_into().ok()?));
+    let le32 = |i: usize| Some(i32::from_le_bytes(header.get(i..i + 4)?.try_into().ok()?));
+
+    if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(("PNG", be32(16)?, be32(20)?));
+    }
+    if header.starts_with(b"GIF8") {
+        return Some(("GIF", le16(6)?, le16(8)?));
+    }
+    if header.starts_with(b"BM") {
+        // Height is negative for top-down bitmaps
+        return Some(("BMP", le32(18)?.unsigned_abs(), le32(22)?.unsigned_abs()));
+    }
+    if header.starts_with(&[0xff, 0xd8]) {
+        // Walk the JPEG segments until a start-of-frame marker
+        let mut i = 2;
+        while i + 4 <= header.len() {
+            if header[i] != 0xff {
+                return None;
+            }
+            let marker = header[i + 1];
+            if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+                return Some(("JPEG", be16(i + 7)?, be16(i + 5)?));
+            }
+            i += 2 + be16(i + 2)? as usize;
+        }
+    }
+
+    None
+}
+
+fn mime_of_extension(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    Some(match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "heic" => "image/heic",
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "mov" => "video/quicktime",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        _ => return None,
+    })
+}
+
+/// Move, copy or link one file into the target directory as `options` say
+pub fn move_file(
+    file_info: &FileInfo,
+    options: &MoveOptions,
+) -> Result<MoveOutcome, Box<dyn std::error::Error>> {
+    if options.mode == TransferMode::Delete {
+        return delete_file(file_info, options);
+    }
+    if !options.fanout.is_empty() {
+        return fan_out_file(file_info, options);
+    }
+    if options.extract {
+        match ArchiveKind::of_name(&file_info.name) {
+            Some(kind) if !file_info.is_dir => {
+                let target_dir = file_target_dir(file_info, &options.target_dir, options);
+                return extract_archive(file_info, kind, &target_dir, options);
+            }
+            _ => warning!(
+                options.quiet,
+                "{}",
+                styled(
+                    format!(
+                        "'{}' is not a zip or tar archive, so it is {} instead",
+                        file_info.name,
+                        options.mode.past_tense()
+                    )
+                    .yellow()
+                )
+            ),
+        }
+    }
+
+    Ok(match transfer_file(file_info, options)? {
+        Some(_) => MoveOutcome::Moved,
+        None => MoveOutcome::Skipped,
+    })
+}
+
+/// Move, copy or link one file into its target directory. Returns where it was put,
+/// or None when it was skipped.
+fn transfer_file(
+    file_info: &FileInfo,
+    options: &MoveOptions,
+) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    let target_dir = file_target_dir(file_info, &options.target_dir, options);
+    let target_dir = target_dir.as_path();
+    let mut target_path = target_dir.join(&file_info.name);
+    let target_name = describe_dir(target_dir);
+
+    if file_info.is_dir {
+        check_not_into_itself(file_info, target_dir, options.mode.verb())?;
+        if options.mode == TransferMode::HardLink {
+            return Err(format!("Cannot hard link directory '{}'", file_info.name).into());
+        }
+    }
+
+    if options.dry_run {
+        let verb = options.mode.verb();
+        let overwrite_note = match (target_path.exists(), options.on_conflict) {
+            (false, _) => "",
+            (true, OnConflict::Skip) => {
+                println!(
+                    "Would skip '{}', '{}' already exists",
+                    file_info.path.display(),
+                    target_path.display()
+                );
+                return Ok(None);
+            }
+            (true, OnConflict::Rename) => {
+                target_path = unique_target_path(target_dir, &file_info.name);
+                " (renamed, a file with that name exists)"
+            }
+            (true, OnConflict::Overwrite) => " (overwrites existing file)",
+            (true, OnConflict::Prompt) => " (asks before overwriting existing file)",
+        };
+        let link_note = if options.leave_link && options.mode == TransferMode::Move {
+            ", leaving a link behind"
+        } else {
+            ""
+        };
+        println!(
+            "Would {} '{}' -> '{}'{}{}",
+            verb,
+            file_info.path.display(),
+            target_path.display(),
+            overwrite_note,
+            link_note
+        );
+        return Ok(Some(target_path));
+    }
+
+    // Check if file already exists in the target directory
+    if target_path.exists() {
+        match options.on_conflict {
+            OnConflict::Overwrite => {}
+            OnConflict::Rename => target_path = unique_target_path(target_dir, &file_info.name),
+            OnConflict::Skip => {
+                warning!(
+                    options.quiet,
+                    "{}",
+                    styled(
+                        format!(
+                            "Skipped '{}', it already exists in {}",
+                            file_info.name, target_name
+                        )
+                        .yellow()
+                    )
+                );
+                return Ok(None);
+            }
+            OnConflict::Prompt => {
+                const RENAME: &str = "Keep both";
+                const OVERWRITE: &str = "Overwrite";
+                const CANCEL: &str = "Cancel";
+
+                let renamed_path = unique_target_path(target_dir, &file_info.name);
+                let renamed_name = renamed_path.file_name().unwrap().to_string_lossy();
+                // Overwriting a file changed after the download is most likely a mistake
+                let (message, help) = if destination_is_newer(&file_info.path, &target_path) {
+                    (
+                        format!(
+                            "File '{}' already exists and the destination is newer!",
+                            file_info.name
+                        ),
+                        format!(
+                            "The existing file was modified after this one, overwriting it loses those changes. Keep both renames the new file to '{}'",
+                            renamed_name
+                        ),
+                    )
+                } else {
+                    (
+                        format!("File '{}' already exists:", file_info.name),
+                        format!(
+                            "Keep both renames the new file to '{}', overwrite permanently replaces the existing file",
+                            renamed_name
+                        ),
+                    )
+                };
+                let choice = Select::new(&message, vec![RENAME, OVERWRITE, CANCEL])
+                    .with_help_message(&help)
+                    .prompt()?;
+
+                match choice {
+                    RENAME => target_path = renamed_path,
+                    OVERWRITE => {}
+                    _ => {
+                        report!(options.quiet, "Operation canceled");
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    fs::create_dir_all(target_dir)?;
+
+    // Links and directories cannot replace an existing entry in place, and
+    // overwriting was confirmed above
+    let replaces_in_place = options.mode != TransferMode::HardLink
+        && options.mode != TransferMode::Symlink
+        && !file_info.is_dir
+        && !target_path.is_dir();
+    if !replaces_in_place {
+        remove_existing(&target_path)?;
+    }
+
+    match options.mode {
+        TransferMode::Copy => {
+            copy_entry(file_info, &target_path, options)?;
+        }
+        TransferMode::HardLink => {
+            let what = format!("Linking '{}'", file_info.name);
+            options
+                .retry
+                .run(&what, || fs::hard_link(&file_info.path, &target_path))?;
+        }
+        TransferMode::Symlink => {
+            let source = std::path::absolute(&file_info.path)?;
+            let what = format!("Linking '{}'", file_info.name);
+            options
+                .retry
+                .run(&what, || create_symlink(&source, &target_path))?;
+        }
+        TransferMode::Delete => unreachable!("deleting returns before the transfer"),
+        TransferMode::Move => {
+            if !rename_or_copy(file_info, &target_path, options, |from, to| {
+                fs::rename(from, to)
+            })? {
+                return Ok(Some(target_path));
+            }
+        }
+    }
+
+    if options.remember_destination
+        && let Err(e) = record_destination(&file_info.path, &options.target_dir)
+    {
+        warning!(
+            options.quiet,
+            "{}",
+            styled(format!("Failed to remember the destination: {}", e).yellow())
+        );
+    }
+
+    let target_file_name = target_path.file_name().unwrap().to_string_lossy();
+    let renamed_note = if target_file_name != file_info.name {
+        format!(" as '{}'", target_file_name)
+    } else {
+        String::new()
+    };
+    report!(
+        options.quiet,
+        "{}",
+        styled(
+            format!(
+                "Successfully {} '{}' to {}{}",
+                options.mode.past_tense(),
+                file_info.name,
+                target_name,
+                renamed_note
+            )
+            .green()
+        )
+    );
+
+    if options.mode == TransferMode::Move {
+        record_moved(file_info, &target_path, options);
+    }
+
+    if let Some(hook) = &options.post_move_hook {
+        run_post_move_hook(hook, &file_info.path, &target_path, options.quiet);
+    }
+
+    Ok(Some(target_path))
+}
+
+/// Once the original is gone, add the move to the history for `m undo` and leave
+/// a link behind with `leave_link`
+fn record_moved(file_info: &FileInfo, target_path: &Path, options: &MoveOptions) {
+    if let Err(e) = record_move(&file_info.path, target_path) {
+        warning!(
+            options.quiet,
+            "{}",
+            styled(format!("Failed to record move in history: {}", e).yellow())
+        );
+    }
+    if options.leave_link {
+        leave_link(file_info, target_path, options.quiet);
+    }
+}
+
+/// Move a file with `rename`, which is atomic and instant but only works within one
+/// filesystem, or else copy it and remove the original. With `use_trash` the original
+/// is always copied and then trashed so it can be restored. Returns false when the
+/// copy was made but the original could not be removed.
+fn rename_or_copy(
+    file_info: &FileInfo,
+    target_path: &Path,
+    options: &MoveOptions,
+    rename: impl Fn(&Path, &Path) -> io::Result<()>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let what = format!("Moving '{}'", file_info.name);
+    let renamed = !options.use_trash
+        && match options
+            .retry
+            .run(&what, || rename(&file_info.path, target_path))
+        {
+            Ok(()) => true,
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => false,
+            Err(e) => return Err(e.into()),
+        };
+    if renamed {
+        return Ok(true);
+    }
+
+    // A failed verification returns before the original is removed
+    copy_entry(file_info, target_path, options)?;
+    Ok(remove_copied_original(file_info, options, "copied"))
+}
+
+/// Below the target directory, files go into the subdirectory for their type,
+/// and then the one for their date
+fn file_target_dir(file_info: &FileInfo, target_dir: &Path, options: &MoveOptions) -> PathBuf {
+    let mut subdir = PathBuf::new();
+    if !file_info.is_dir
+        && let Some(routed) = routed_subdir(&options.type_routing, &file_info.path)
+    {
+        subdir.push(routed);
+    }
+    if let Some(format) = &options.date_subdir {
+        subdir.push(format_time_with(file_info.created_timestamp, format));
+    }
+    target_dir.join(subdir)
+}
+
+/// Move a batch of files all or nothing. Every file is copied to each of its targets and
+/// verified before any original is removed, and if one copy fails, the copies made so far
+/// are removed again. Nothing is overwritten, a copy whose name is taken is numbered.
+pub fn move_atomically(
+    batch: &[(&FileInfo, &MoveOptions)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut copies: Vec<PathBuf> = Vec::new();
+    let mut placed: Vec<(&FileInfo, &MoveOptions, Vec<PathBuf>)> = Vec::new();
+    for &(file_info, options) in batch {
+        let mut verified = options.clone();
+        verified.verify = true;
+        let mut targets = Vec::new();
+        for dir in std::iter::once(&options.target_dir).chain(&options.fanout) {
+            let target_dir = file_target_dir(file_info, dir, options);
+            match copy_to_free_path(file_info, &target_dir, &verified) {
+                Ok(target) => {
+                    copies.push(target.clone());
+                    targets.push(target);
+                }
+                Err(e) => {
+                    let rollback = copies
+                        .iter()
+                        .filter_map(|copy| {
+                            remove_existing(copy)
+                                .err()
+                                .map(|e| format!("'{}' ({})", copy.display(), e))
+                        })
+                        .collect::<Vec<_>>();
+                    let outcome = if copies.is_empty() {
+                        "All originals are in place".to_string()
+                    } else if rollback.is_empty() {
+                        "The copies made before it were removed and all originals are in place"
+                            .to_string()
+                    } else {
+                        format!(
+                            "All originals are in place, but these copies could not be removed: {}",
+                            rollback.join(", ")
+                        )
+                    };
+                    return Err(format!(
+                        "Failed to copy '{}' to {}: {}. {}",
+                        file_info.name,
+                        describe_dir(&target_dir),
+                        e,
+                        outcome
+                    )
+                    .into());
+                }
+            }
+        }
+        placed.push((file_info, options, targets));
+    }
+
+    // Every file is safely at its destinations now, so a failed removal only leaves a duplicate
+    for (file_info, options, targets) in placed {
+        if remove_copied_original(file_info, options, "copied")
+            && let [target] = targets.as_slice()
+            && let Err(e) = record_move(&file_info.path, target)
+        {
+            warning!(
+                options.quiet,
+                "{}",
+                styled(format!("Failed to record move in history: {}", e).yellow())
+            );
+        }
+        if options.remember_destination
+            && let Err(e) = record_destination(&file_info.path, &options.target_dir)
+        {
+            warning!(
+                options.quiet,
+                "{}",
+                styled(format!("Failed to remember the destination: {}", e).yellow())
+            );
+        }
+        for target in &targets {
+            report!(
+                options.quiet,
+                "{}",
+                styled(format!("Moved '{}' -> '{}'", file_info.name, target.display()).green())
+            );
+            if let Some(hook) = &options.post_move_hook {
+                run_post_move_hook(hook, &file_info.path, target, options.quiet);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copy into `target_dir` under the file's name, or a numbered one if that is taken.
+/// A failed copy is removed, the path was free before.
+fn copy_to_free_path(
+    file_info: &FileInfo,
+    target_dir: &Path,
+    options: &MoveOptions,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if file_info.is_dir {
+        check_not_into_itself(file_info, target_dir, options.mode.verb())?;
+    }
+    fs::create_dir_all(target_dir)?;
+    let mut target = target_dir.join(&file_info.name);
+    if fs::symlink_metadata(&target).is_ok() {
+        target = unique_target_path(target_dir, &file_info.name);
+    }
+    if let Err(e) = copy_entry(file_info, &target, options) {
+        let _ = remove_existing(&target);
+        return Err(e);
+    }
+    Ok(target)
+}
+
+/// Moving a directory into its own subtree would never finish
+fn check_not_into_itself(
+    file_info: &FileInfo,
+    target_dir: &Path,
+    verb: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source = fs::canonicalize(&file_info.path)?;
+    let destination = fs::canonicalize(target_dir).or_else(|_| std::path::absolute(target_dir))?;
+    if destination.join(&file_info.name).starts_with(&source) {
+        return Err(format!("Cannot {} '{}' into itself", verb, file_info.path.display()).into());
+    }
+    Ok(())
+}
+
+/// Delete the file, or send it to the trash with `use_trash`. Asking first is up to the caller.
+fn delete_file(
+    file_info: &FileInfo,
+    options: &MoveOptions,
+) -> Result<MoveOutcome, Box<dyn std::error::Error>> {
+    let what = if options.use_trash { "trash" } else { "delete" };
+    if options.dry_run {
+        println!("Would {} '{}'", what, file_info.path.display());
+        return Ok(MoveOutcome::Moved);
+    }
+
+    remove_original(&file_info.path, options.use_trash)
+        .map_err(|e| format!("Failed to {} '{}': {}", what, file_info.name, e))?;
+    let message = if options.use_trash {
+        format!("Moved '{}' to the trash", file_info.name)
+    } else {
+        format!("Deleted '{}'", file_info.name)
+    };
+    report!(options.quiet, "{}", styled(message.green()));
+    Ok(MoveOutcome::Moved)
+}
+
+/// Whether the file already at the target was modified after the one replacing it
+fn destination_is_newer(source: &Path, target: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    matches!((modified(source), modified(target)), (Ok(source), Ok(target)) if target > source)
+}
+
+/// The `type_routing` subdirectory for a file's extension, if there is one
+fn routed_subdir<'a>(routing: &'a BTreeMap<String, Vec<String>>, path: &Path) -> Option<&'a str> {
+    let ext = path.extension()?.to_string_lossy();
+    routing
+        .iter()
+        .find(|(_, extensions)| {
+            extensions
+                .iter()
+                .any(|routed| routed.trim_start_matches('.').eq_ignore_ascii_case(&ext))
+        })
+        .map(|(subdir, _)| subdir.as_str())
+}
+
+/// Copy (or link) a file into the target directory and each `fanout` directory,
+/// each with its own conflict handling. When moving, the original is only
+/// removed once every directory got a copy.
+fn fan_out_file(
+    file_info: &FileInfo,
+    options: &MoveOptions,
+) -> Result<MoveOutcome, Box<dyn std::error::Error>> {
+    let targets: Vec<PathBuf> = std::iter::once(options.target_dir.clone())
+        .chain(options.fanout.iter().cloned())
+        .collect();
+    let mut single = options.clone();
+    single.fanout.clear();
+    if single.mode == TransferMode::Move {
+        single.mode = TransferMode::Copy;
+    }
+
+    let mut copied = 0;
+    let mut failures = Vec::new();
+    // Where the copy in the target directory went, which undo moves back
+    let mut first_copy = None;
+    for target in &targets {
+        single.target_dir = target.clone();
+        match transfer_file(file_info, &single) {
+            Ok(Some(path)) => {
+                copied += 1;
+                first_copy.get_or_insert(path);
+            }
+            Ok(None) => {}
+            Err(e) => failures.push(format!("{}: {}", describe_dir(target), e)),
+        }
+    }
+
+    if !failures.is_empty() {
+        let kept = if options.mode == TransferMode::Move {
+            ", so the original was kept"
+        } else {
+            ""
+        };
+        return Err(format!(
+            "'{}' reached {} of {} destinations{}. {}",
+            file_info.name,
+            copied,
+            targets.len(),
+            kept,
+            failures.join("; ")
+        )
+        .into());
+    }
+    if copied == 0 {
+        return Ok(MoveOutcome::Skipped);
+    }
+
+    if options.mode == TransferMode::Move {
+        if copied < targets.len() {
+            warning!(
+                options.quiet,
+                "{}",
+                styled(
+                    format!(
+                        "Kept '{}', it was skipped in {} of {} destinations",
+                        file_info.name,
+                        targets.len() - copied,
+                        targets.len()
+                    )
+                    .yellow()
+                )
+            );
+        } else if options.dry_run {
+            println!("Would delete '{}'", file_info.path.display());
+        } else if remove_copied_original(file_info, options, "copied") {
+            report!(
+                options.quiet,
+                "{}",
+                styled(
+                    format!(
+                        "Successfully moved '{}' to {} destinations",
+                        file_info.name,
+                        targets.len()
+                    )
+                    .green()
+                )
+            );
+            if let Some(first_copy) = &first_copy {
+                record_moved(file_info, first_copy, options);
+            }
+        }
+    }
+    Ok(MoveOutcome::Moved)
+}
+
+/// Put a symlink to the moved file where it was, so existing references keep working.
+/// Failing only warns, as the file itself was moved.
+fn leave_link(file_info: &FileInfo, target_path: &Path, quiet: bool) {
+    let result = fs::canonicalize(target_path)
+        .or_else(|_| std::path::absolute(target_path))
+        .and_then(|target| {
+            create_symlink(&target, &file_info.path)?;
+            Ok(target)
+        });
+    match result {
+        Ok(target) => report!(
+            quiet,
+            "{}",
+            styled(
+                format!(
+                    "Left a link at '{}' pointing to '{}'",
+                    file_info.path.display(),
+                    target.display()
+                )
+                .green()
+            )
+        ),
+        Err(e) => warning!(
+            quiet,
+            "{}",
+            styled(
+                format!(
+                    "Moved '{}', but could not leave a link at '{}': {}",
+                    file_info.name,
+                    file_info.path.display(),
+                    e
+                )
+                .yellow()
+            )
+        ),
+    }
+}
+
+/// Run the post-move hook through the shell. The hook failing only warns, as the
+/// move itself succeeded.
+fn run_post_move_hook(hook: &str, source: &Path, target: &Path, quiet: bool) {
+    let target = std::path::absolute(target).unwrap_or_else(|_| target.to_path_buf());
+    let name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let command = hook
+        .replace("{src}", &shell_quote(&source.to_string_lossy()))
+        .replace("{dst}", &shell_quote(&target.to_string_lossy()))
+        .replace("{name}", &shell_quote(&name));
+
+    let status = if cfg!(windows) {
+        process::Command::new("cmd")
+            .arg("/C")
+            .arg(&command)
+            .status()
+    } else {
+        process::Command::new("sh").arg("-c").arg(&command).status()
+    };
+    let problem = match status {
+        Ok(status) if status.success() => return,
+        Ok(status) => format!("exited with {}", status),
+        Err(e) => format!("could not be run: {}", e),
+    };
+    warning!(
+        quiet,
+        "{}",
+        styled(format!("Post-move hook '{}' {}", command, problem).yellow())
+    );
+}
+
+/// Quote a value so the shell passes it to the hook as a single argument
+fn shell_quote(value: &str) -> String {
+    if cfg!(windows) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Upload a file to a remote directory with the `sftp` client, then delete the
+/// original unless copying. A file of the same name on the remote host is handled
+/// per `on_conflict`, and the original is only removed once the uploaded file's
+/// SHA-256 matches it. Authentication uses the ssh agent or keys, as there is no
+/// way to ask for a password in batch mode.
+///
+/// The system OpenSSH client is used rather than a library such as `ssh2` so that
+/// host aliases, ports, `ProxyJump` and keys from `~/.ssh/config`, and the user's
+/// `known_hosts`, work as they do for `ssh`. It also keeps libssh2 and OpenSSL out
+/// of the build, and Windows 10 and later ship the same client.
+pub fn upload_file(
+    file_info: &FileInfo,
+    remote: &RemoteTarget,
+    options: &MoveOptions,
+) -> Result<MoveOutcome, Box<dyn std::error::Error>> {
+    if file_info.is_dir {
+        return Err(format!(
+            "Cannot upload directory '{}', only files can be sent to a remote destination",
+            file_info.name
+        )
+        .into());
+    }
+    let remote_path_of = |name: &str| match remote.dir.as_str() {
+        "" => name.to_string(),
+        dir if dir.ends_with('/') => format!("{}{}", dir, name),
+        dir => format!("{}/{}", dir, name),
+    };
+    let mut remote_path = remote_path_of(&file_info.name);
+
+    if options.dry_run {
+        println!(
+            "Would upload '{}' -> '{}:{}'",
+            file_info.path.display(),
+            remote.host,
+            remote_path
+        );
+        return Ok(MoveOutcome::Moved);
+    }
+
+    // sftp's put replaces an existing file without asking
+    let exists = remote_exists(&remote.host, &remote_path)?;
+    if exists {
+        let free_path = || -> Result<String, String> {
+            for n in 1.. {
+                let path = remote_path_of(&numbered_name(&file_info.name, n));
+                if !remote_exists(&remote.host, &path)? {
+                    return Ok(path);
+                }
+            }
+            unreachable!()
+        };
+        match options.on_conflict {
+            OnConflict::Overwrite => {}
+            OnConflict::Rename => remote_path = free_path()?,
+            OnConflict::Skip => {
+                warning!(
+                    options.quiet,
+                    "{}",
+                    styled(
+                        format!(
+                            "Skipped '{}', it already exists on '{}'",
+                            file_info.name, remote.host
+                        )
+                        .yellow()
+                    )
+                );
+                return Ok(MoveOutcome::Skipped);
+            }
+            OnConflict::Prompt => {
+                const RENAME: &str = "Keep both";
+                const OVERWRITE: &str = "Overwrite";
+                const CANCEL: &str = "Cancel";
+
+                let renamed_path = free_path()?;
+                let renamed_name = renamed_path.rsplit('/').next().unwrap_or(&renamed_path);
+                let help = format!(
+                    "Keep both uploads the file as '{}', overwrite permanently replaces the existing file",
+                    renamed_name
+                );
+                let choice = Select::new(
+                    &format!(
+                        "File '{}' already exists on '{}':",
+                        file_info.name, remote.host
+                    ),
+                    vec![RENAME, OVERWRITE, CANCEL],
+                )
+                .with_help_message(&help)
+                .prompt()?;
+
+                match choice {
+                    RENAME => remote_path = renamed_path,
+                    OVERWRITE => {}
+                    _ => {
+                        report!(options.quiet, "Operation canceled");
+                        return Ok(MoveOutcome::Skipped);
+                    }
+                }
+            }
+        }
+    }
+    // Only a file this upload created may be removed again
+    let replaces = exists && remote_path == remote_path_of(&file_info.name);
+
+    let local_path = std::path::absolute(&file_info.path)?;
+    let batch = format!(
+        "put -p {} {}\n",
+        sftp_quote(&local_path.to_string_lossy()),
+        sftp_quote(&remote_path)
+    );
+    let mut child = process::Command::new("sftp")
+        .args(["-b", "-", "--", &remote.host])
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run sftp, is OpenSSH installed? {}", e))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(batch.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(remote_error(&remote.host, &output.stderr).into());
+    }
+
+    let verify = options.verify || options.mode == TransferMode::Move;
+    if verify {
+        let expected = hash_file(&file_info.path)?;
+        match remote_hash(&remote.host, &remote_path) {
+            Ok(actual) if actual == expected => {}
+            Ok(_) if replaces => {
+                return Err(format!(
+                    "The upload of '{}' does not match the original, which was kept. It replaced '{}:{}', so upload it again",
+                    file_info.name, remote.host, remote_path
+                )
+                .into());
+            }
+            Ok(_) => {
+                // Do not leave a corrupt copy behind
+                let _ = run_ssh(
+                    &remote.host,
+                    &format!("rm -f -- {}", shell_quote(&remote_path)),
+                );
+                return Err(format!(
+                    "The upload of '{}' does not match the original, so nothing was changed",
+                    file_info.name
+                )
+                .into());
+            }
+            Err(e) => {
+                return Err(format!(
+                    "Could not check the upload of '{}', so the original was kept: {}",
+                    file_info.name, e
+                )
+                .into());
+            }
+        }
+    }
+
+    if options.mode == TransferMode::Move && !remove_copied_original(file_info, options, "uploaded")
+    {
+        return Ok(MoveOutcome::Moved);
+    }
+
+    report!(
+        options.quiet,
+        "{}",
+        styled(
+            format!(
+                "Successfully uploaded '{}' to '{}:{}'",
+                file_info.name, remote.host, remote_path
+            )
+            .green()
+        )
+    );
+    Ok(MoveOutcome::Moved)
+}
+
+/// Whether anything, a dangling link included, is at `path` on the remote host
+fn remote_exists(host: &str, path: &str) -> Result<bool, String> {
+    let path = shell_quote(path);
+    let output = run_ssh(
+        host,
+        &format!(
+            "if [ -e {} ] || [ -L {} ]; then echo exists; fi",
+            path, path
+        ),
+    )?;
+    Ok(output.trim() == "exists")
+}
+
+/// SHA-256 of a remote file, with `sha256sum` or, on macOS and the BSDs, `shasum`
+fn remote_hash(host: &str, path: &str) -> Result<String, String> {
+    let path = shell_quote(path);
+    let output = run_ssh(
+        host,
+        &format!(
+            "sha256sum -- {} 2>/dev/null || shasum -a 256 -- {}",
+            path, path
+        ),
+    )?;
+    output
+        .split_whitespace()
+        .next()
+        .map(str::to_lowercase)
+        .ok_or_else(|| "the remote host printed no checksum".to_string())
+}
+
+/// Run a command on the remote host, returning its output
+fn run_ssh(host: &str, command: &str) -> Result<String, String> {
+    let output = process::Command::new("ssh")
+        .args(["-o", "BatchMode=yes", "--", host, command])
+        .stdin(process::Stdio::null())
+        .output()
+        .map_err(|e| format!("Could not run ssh, is OpenSSH installed? {}", e))?;
+    if !output.status.success() {
+        return Err(remote_error(host, &output.stderr));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Explain the common ssh failures, which are otherwise easy to miss among its output
+fn remote_error(host: &str, stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let detail = stderr.trim();
+    if detail.contains("Host key verification failed") {
+        format!(
+            "The host key of '{}' is unknown or has changed. Connect once with `ssh {}` to check and accept it",
+            host, host
+        )
+    } else if detail.contains("Permission denied") {
+        format!(
+            "Could not log in to '{}'. Uploads need a key or an ssh agent, as there is no way to ask for a password",
+            host
+        )
+    } else if detail.is_empty() {
+        format!("The transfer to '{}' failed", host)
+    } else {
+        format!("The transfer to '{}' failed: {}", host, detail)
+    }
+}
+
+/// Quote a path for an sftp batch command, which takes backslash escapes within double quotes
+fn sftp_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The archive formats `--extract` unpacks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    fn of_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else {
+            None
+        }
+    }
+
+    /// The archive's name without the archive extension, for the directory it is extracted into
+    fn stem(self, name: &str) -> &str {
+        let extension = match self {
+            ArchiveKind::Zip => ".zip",
+            ArchiveKind::Tar => ".tar",
+            ArchiveKind::TarGz if name.to_lowercase().ends_with(".tgz") => ".tgz",
+            ArchiveKind::TarGz => ".tar.gz",
+        };
+        let stem = &name[..name.len() - extension.len()];
+        if stem.is_empty() { name } else { stem }
+    }
+}
+
+/// What extracting an archive wrote
+#[derive(Debug, Default)]
+struct Extracted {
+    files: usize,
+    // Links, special files and entries that would end up outside the destination
+    skipped: usize,
+}
+
+/// Extract an archive into a new directory named after it in the target directory,
+/// numbered when the name is taken so nothing is overwritten. With `delete_archive`,
+/// the archive is removed once everything was extracted.
+fn extract_archive(
+    file_info: &FileInfo,
+    kind: ArchiveKind,
+    target_dir: &Path,
+    options: &MoveOptions,
+) -> Result<MoveOutcome, Box<dyn std::error::Error>> {
+    let stem = kind.stem(&file_info.name);
+    let mut destination = target_dir.join(stem);
+    if destination.exists() {
+        destination = unique_target_path(target_dir, stem);
+    }
+
+    if options.dry_run {
+        println!(
+            "Would extract '{}' -> '{}'",
+            file_info.path.display(),
+            destination.display()
+        );
+        return Ok(MoveOutcome::Moved);
+    }
+
+    fs::create_dir_all(&destination)?;
+    let limits = ExtractLimits::for_archive(fs::metadata(&file_info.path)?.len());
+    let result = match kind {
+        ArchiveKind::Zip => extract_zip(&file_info.path, &destination, limits),
+        ArchiveKind::Tar => extract_tar(fs::File::open(&file_info.path)?, &destination, limits),
+        ArchiveKind::TarGz => extract_tar(
+            GzDecoder::new(fs::File::open(&file_info.path)?),
+            &destination,
+            limits,
+        ),
+    };
+    let extracted = match result {
+        Ok(extracted) => extracted,
+        Err(e) => {
+            // Do not leave half an archive behind
+            let _ = fs::remove_dir_all(&destination);
+            return Err(format!("Could not extract '{}': {}", file_info.name, e).into());
+        }
+    };
+
+    let skipped_note = match extracted.skipped {
+        0 => String::new(),
+        1 => ", skipping 1 link or unsafe entry".to_string(),
+        n => format!(", skipping {} links or unsafe entries", n),
+    };
+    report!(
+        options.quiet,
+        "{}",
+        styled(
+            format!(
+                "Successfully extracted {} file{} from '{}' to '{}'{}",
+                extracted.files,
+                if extracted.files == 1 { "" } else { "s" },
+                file_info.name,
+                destination.display(),
+                skipped_note
+            )
+            .green()
+        )
+    );
+
+    if options.delete_archive {
+        remove_copied_original(file_info, options, "extracted");
+    }
+    Ok(MoveOutcome::Moved)
+}
+
+/// Where an archive entry goes, or None for names that would end up outside the
+/// destination, such as absolute paths or ones containing `..`
+fn archive_entry_path(destination: &Path, name: &str) -> Option<PathBuf> {
+    let mut path = destination.to_path_buf();
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => return None,
+            part if Path::new(part).has_root() || part.contains(':') => return None,
+            part => path.push(part),
+        }
+    }
+    (path != destination).then_some(path)
+}
+
+fn invalid_archive(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// How much an archive may hold, so a malicious one cannot fill the disk
+#[derive(Debug)]
+struct ExtractLimits {
+    entries: usize,
+    bytes: u64,
+}
+
+impl ExtractLimits {
+    // Deflate compresses at most about 1000 to 1, anything more is a zip bomb
+    const MAX_RATIO: u64 = 2000;
+    const MAX_ENTRIES: usize = 100_000;
+
+    fn for_archive(archive_size: u64) -> Self {
+        ExtractLimits {
+            entries: Self::MAX_ENTRIES,
+            bytes: archive_size
+                .saturating_mul(Self::MAX_RATIO)
+                .saturating_add(1 << 20),
+        }
+    }
+
+    fn count_entry(&mut self) -> io::Result<()> {
+        self.entries = self
+            .entries
+            .checked_sub(1)
+            .ok_or_else(|| invalid_archive("the archive has too many entries"))?;
+        Ok(())
+    }
+}
+
+/// Write an archive entry's contents, creating its parent directories
+fn write_archive_file(
+    path: &Path,
+    contents: &mut impl Read,
+    limits: &mut ExtractLimits,
+) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = io::BufWriter::new(fs::File::create(path)?);
+    let written = io::copy(&mut contents.take(limits.bytes + 1), &mut writer)?;
+    if written > limits.bytes {
+        return Err(invalid_archive(
+            "the archive unpacks to far more than its size, it may be a zip bomb",
+        ));
+    }
+    limits.bytes -= written;
+    writer.flush()
+}
+
+#[cfg(unix)]
+fn set_archive_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))
+}
+
+#[cfg(not(unix))]
+fn set_archive_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Extract a tar stream. Only files and directories are written.
+fn extract_tar(
+    reader: impl Read,
+    destination: &Path,
+    mut limits: ExtractLimits,
+) -> io::Result<Extracted> {
+    let mut archive = tar::Archive::new(reader);
+    let mut extracted = Extracted::default();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        limits.count_entry()?;
+        // Long GNU and pax names are already applied here
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        match entry.header().entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                match archive_entry_path(destination, &name) {
+                    Some(path) => {
+                        let mode = entry.header().mode();
+                        write_archive_file(&path, &mut entry, &mut limits)?;
+                        if let Ok(mode) = mode {
+                            set_archive_mode(&path, mode)?;
+                        }
+                        extracted.files += 1;
+                    }
+                    None => extracted.skipped += 1,
+                }
+            }
+            tar::EntryType::Directory => {
+                if let Some(path) = archive_entry_path(destination, &name) {
+                    fs::create_dir_all(path)?;
+                }
+            }
+            // Global pax headers carry nothing needed here
+            tar::EntryType::XGlobalHeader => {}
+            _ => extracted.skipped += 1,
+        }
+    }
+    Ok(extracted)
+}
+
+/// Extract a zip archive, whose entries are checked against their CRC as they are read
+fn extract_zip(
+    archive: &Path,
+    destination: &Path,
+    mut limits: ExtractLimits,
+) -> io::Result<Extracted> {
+    let zip_error = |e: zip::result::ZipError| invalid_archive(e.to_string());
+    let mut archive = zip::ZipArchive::new(fs::File::open(archive)?).map_err(zip_error)?;
+    let mut extracted = Extracted::default();
+    for index in 0..archive.len() {
+        limits.count_entry()?;
+        let mut entry = archive.by_index(index).map_err(zip_error)?;
+        let name = entry.name().to_string();
+        let Some(path) = archive_entry_path(destination, &name) else {
+            if !entry.is_dir() {
+                extracted.skipped += 1;
+            }
+            continue;
+        };
+        if entry.is_dir() {
+            fs::create_dir_all(path)?;
+            continue;
+        }
+        if entry.is_symlink() {
+            extracted.skipped += 1;
+            continue;
+        }
+        write_archive_file(&path, &mut entry, &mut limits)?;
+        if let Some(mode) = entry.unix_mode() {
+            set_archive_mode(&path, mode)?;
+        }
+        extracted.files += 1;
+    }
+    Ok(extracted)
+}
+
+/// Find the first free name in the target directory by numbering the file name
+/// before its extension: `report.pdf` becomes `report (1).pdf`, `report (2).pdf`, ...
+fn unique_target_path(target_dir: &Path, name: &str) -> PathBuf {
+    (1..)
+        .map(|n| target_dir.join(numbered_name(name, n)))
+        .find(|path| fs::symlink_metadata(path).is_err())
+        .unwrap()
+}
+
+/// `name` with `n` added before the extension, as in `report (2).pdf`
+fn numbered_name(name: &str, n: u32) -> String {
+    let name_path = Path::new(name);
+    let stem = name_path
+        .file_stem()
+        .map_or(name.into(), |stem| stem.to_string_lossy());
+    let extension = name_path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    format!("{} ({}){}", stem, n, extension)
+}
+
+/// Remove the original once its contents are safely elsewhere. A failure only
+/// leaves a duplicate behind, so it is reported and false returned.
+fn remove_copied_original(file_info: &FileInfo, options: &MoveOptions, done: &str) -> bool {
+    let Err(e) = remove_original(&file_info.path, options.use_trash) else {
+        return true;
+    };
+    warning!(
+        options.quiet,
+        "{}",
+        styled(
+            format!(
+                "File '{}' was {}, but failed to {} the original: {}",
+                file_info.name,
+                done,
+                if options.use_trash { "trash" } else { "delete" },
+                e
+            )
+            .yellow()
+        )
+    );
+    false
+}
+
+fn remove_original(path: &Path, use_trash: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if use_trash {
+        trash::delete(path)?;
+    } else if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Remove whatever is at `path`, without following a symlink there
+fn remove_existing(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+fn copy_entry(
+    file_info: &FileInfo,
+    target: &Path,
+    options: &MoveOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let what = format!("Copying '{}'", file_info.name);
+    options.retry.run(&what, || {
+        if file_info.is_dir {
+            copy_dir_recursive(&file_info.path, target)
+        } else {
+            copy_with_progress(&file_info.path, target, file_info.size, options)
+        }
+    })?;
+
+    if options.verify && !contents_match(&file_info.path, target)? {
+        // Do not leave a corrupt copy behind
+        remove_existing(target)?;
+        return Err(format!(
+            "The copy of '{}' does not match the original, so nothing was changed. The target storage may be faulty",
+            file_info.name
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Compare a file or directory tree with its copy by size and SHA-256 of every file
+fn contents_match(source: &Path, copy: &Path) -> io::Result<bool> {
+    let metadata = fs::symlink_metadata(source)?;
+    if metadata.is_symlink() {
+        return Ok(fs::read_link(source)? == fs::read_link(copy)?);
+    }
+    if metadata.is_dir() {
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            if !contents_match(&entry.path(), &copy.join(entry.file_name()))? {
+                return Ok(false);
+            }
+        }
+        return Ok(true);
+    }
+
+    Ok(metadata.len() == fs::metadata(copy)?.len() && hash_file(source)? == hash_file(copy)?)
+}
+
+/// Copy a directory tree, recreating symlinks instead of following them
+fn copy_dir_recursive(source: &Path, target: &Path) -> io::Result<()> {
+    fs::create_dir(target)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let destination = target.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_dir_recursive(&entry.path(), &destination)?;
+        } else if file_type.is_symlink() {
+            create_symlink(&fs::read_link(entry.path())?, &destination)?;
+        } else {
+            fs::copy(entry.path(), &destination)?;
+        }
+    }
+    fs::set_permissions(target, fs::metadata(source)?.permissions())
+}
+
+/// Copy a file, showing a progress bar when it is large enough for the copy to take a while
+fn copy_with_progress(
+    source: &Path,
+    target: &Path,
+    size: u64,
+    options: &MoveOptions,
+) -> io::Result<()> {
+    const PROGRESS_THRESHOLD: u64 = 50 * 1024 * 1024;
+
+    if size < PROGRESS_THRESHOLD || options.quiet {
+        return fs::copy(source, target).map(|_| ());
+    }
+
+    let mut style =
+        ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} {bytes_per_sec} ETA {eta}")
+            .unwrap();
+    if options.ascii {
+        style = style.progress_chars("#>-");
+    }
+    let progress = ProgressBar::new(size).with_style(style);
+
+    let mut reader = fs::File::open(source)?;
+    let mut writer = io::BufWriter::new(fs::File::create(target)?);
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buffer[..read])?;
+        progress.inc(read as u64);
+    }
+    writer.flush()?;
+    progress.finish_and_clear();
+
+    // Match fs::copy, which carries the permissions over
+    fs::set_permissions(target, fs::metadata(source)?.permissions())?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+/// Windows distinguishes links to files from links to directories
+#[cfg(windows)]
+fn create_symlink(original: &Path, link: &Path) -> io::Result<()> {
+    if original.is_dir() {
+        std::os::windows::fs::symlink_dir(original, link)
+    } else {
+        std::os::windows::fs::symlink_file(original, link)
+    }
+}
+
+fn history_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(config_dir()?.join("history.json"))
+}
+
+fn read_history() -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
+    let history_path = history_path()?;
+    if !history_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let json_content = fs::read_to_string(&history_path)?;
+    Ok(serde_json::from_str(&json_content)?)
+}
+
+fn write_history(history: &[HistoryEntry]) -> Result<(), Box<dyn std::error::Error>> {
+    let history_path = history_path()?;
+    fs::create_dir_all(history_path.parent().unwrap())?;
+    fs::write(&history_path, serde_json::to_string_pretty(history)?)?;
+    Ok(())
+}
+
+fn record_move(source: &Path, destination: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut history = read_history()?;
+    // Store absolute paths so undo works from any directory
+    history.push(HistoryEntry {
+        source: std::path::absolute(source)?,
+        destination: fs::canonicalize(destination)?,
+    });
+    write_history(&history)
+}
+
+fn scan_cache_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(config_dir()?.join("cache.json"))
+}
+
+/// Directory listings saved by the last scan. A missing or unreadable cache is
+/// only a slower scan, so errors give an empty cache.
+fn read_scan_cache() -> HashMap<PathBuf, CachedDir> {
+    scan_cache_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|json_content| serde_json::from_str(&json_content).ok())
+        .unwrap_or_default()
+}
+
+fn write_scan_cache(cache: &HashMap<PathBuf, CachedDir>) -> Result<(), Box<dyn std::error::Error>> {
+    let cache_path = scan_cache_path()?;
+    fs::create_dir_all(cache_path.parent().unwrap())?;
+    fs::write(&cache_path, serde_json::to_string(cache)?)?;
+    Ok(())
+}
+
+fn destinations_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(config_dir()?.join("destinations.json"))
+}
+
+/// Where files from each source directory were last moved to
+fn read_destinations() -> Result<BTreeMap<PathBuf, PathBuf>, Box<dyn std::error::Error>> {
+    let destinations_path = destinations_path()?;
+    if !destinations_path.exists() {
+        return Ok(BTreeMap::new());
+    }
+
+    let json_content = fs::read_to_string(&destinations_path)?;
+    Ok(serde_json::from_str(&json_content)?)
+}
+
+/// Remember `target_dir` as the destination for files from the directory of `source`
+pub fn record_destination(
+    source: &Path,
+    target_dir: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source_dir = std::path::absolute(source)?
+        .parent()
+        .ok_or("File has no parent directory")?
+        .to_path_buf();
+
+    let mut destinations = read_destinations()?;
+    destinations.insert(source_dir, fs::canonicalize(target_dir)?);
+
+    let destinations_path = destinations_path()?;
+    fs::create_dir_all(destinations_path.parent().unwrap())?;
+    fs::write(
+        &destinations_path,
+        serde_json::to_string_pretty(&destinations)?,
+    )?;
+    Ok(())
+}
+
+/// The directory files from the same directory as `file` were last moved to.
+/// Errors reading the map only mean there is nothing to remember.
+pub fn last_destination(file: &FileInfo) -> Option<PathBuf> {
+    let source_dir = std::path::absolute(&file.path)
+        .ok()?
+        .parent()?
+        .to_path_buf();
+    read_destinations().ok()?.remove(&source_dir)
+}
+
+/// Move the most recently moved file back and drop it from the history, saying so
+/// unless `quiet`
+pub fn undo_last_move(quiet: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut history = read_history()?;
+    let entry = history.pop().ok_or("No moves to undo")?;
+
+    if !entry.destination.exists() {
+        // The entry can never be undone, so drop it instead of blocking older ones
+        write_history(&history)?;
+        return Err(format!(
+            "'{}' no longer exists, it may have been deleted or renamed. Removed it from the history",
+            entry.destination.display()
+        )
+        .into());
+    }
+
+    // A link left by --leave-link gives way to the file it points to
+    if fs::read_link(&entry.source)
+        .is_ok_and(|link| fs::canonicalize(&link).ok() == fs::canonicalize(&entry.destination).ok())
+    {
+        fs::remove_file(&entry.source)?;
+    }
+
+    if entry.source.exists() {
+        return Err(format!(
+            "'{}' already exists, refusing to overwrite it",
+            entry.source.display()
+        )
+        .into());
+    }
+
+    if let Some(parent) = entry.source.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Rename when possible, otherwise copy across filesystems
+    if fs::rename(&entry.destination, &entry.source).is_err() {
+        if entry.destination.is_dir() {
+            copy_dir_recursive(&entry.destination, &entry.source)?;
+            fs::remove_dir_all(&entry.destination)?;
+        } else {
+            fs::copy(&entry.destination, &entry.source)?;
+            fs::remove_file(&entry.destination)?;
+        }
+    }
+    write_history(&history)?;
+
+    report!(
+        quiet,
+        "{}",
+        styled(
+            format!(
+                "Moved '{}' back to '{}'",
+                entry.destination.display(),
+                entry.source.display()
+            )
+            .green()
+        )
+    );
+
+    Ok(())
+}
+
+fn format_time(timestamp: u64, now: u64, format: TimeFormat) -> String {
+    match format {
+        TimeFormat::Clock => format_time_with(timestamp, "%H:%M"),
+        TimeFormat::Iso => format_time_with(timestamp, "%Y-%m-%dT%H:%M:%S"),
+        TimeFormat::Relative => {
+            let age = now.saturating_sub(timestamp);
+            if age < 60 {
+                "just now".to_string()
+            } else if age < 60 * 60 {
+                format!("{}m ago", age / 60)
+            } else if age < 24 * 60 * 60 {
+                format!("{}h ago", age / (60 * 60))
+            } else {
+                format!("{}d ago", age / (24 * 60 * 60))
+            }
+        }
+    }
+}
+
+/// Format a timestamp in the local timezone with a chrono format string, which must
+/// have been checked to be valid
+fn format_time_with(timestamp: u64, format: &str) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .unwrap_or_default()
+        .with_timezone(&chrono::Local)
+        .format(format)
+        .to_string()
+}
+
+/// Expand a leading `~` to the home directory and `$VAR`/`${VAR}` to environment variables.
+/// Unset variables are left as written.
+pub fn expand_path(path: &str) -> PathBuf {
+    let mut expanded = String::new();
+    let mut rest = path;
+
+    if let Some(home) = dirs::home_dir() {
+        if rest == "~" {
+            return home;
+        }
+        if let Some(stripped) = rest.strip_prefix("~/").or(rest.strip_prefix("~\\")) {
+            expanded.push_str(&home.to_string_lossy());
+            expanded.push(std::path::MAIN_SEPARATOR);
+            rest = stripped;
+        }
+    }
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let (name, remainder) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", braced),
+            }
+        } else {
+            let end = after
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+
+        match std::env::var(name) {
+            Ok(value) if !name.is_empty() => {
+                expanded.push_str(&value);
+                rest = remainder;
+            }
+            _ => {
+                expanded.push('$');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+
+    PathBuf::from(expanded)
+}
+
+/// How a directory is named in messages
+pub fn describe_dir(dir: &Path) -> String {
+    if dir == Path::new(".") {
+        "current directory".to_string()
+    } else {
+        format!("'{}'", dir.display())
+    }
+}
+
+/// Parse a duration such as "90m" or "2h" as a time limit
+pub fn parse_time_limit(value: &str) -> Result<TimeLimit, String> {
+    parse_duration(value).map(TimeLimit)
+}
+
+/// The time since a reference file was created (or modified, where creation time is
+/// unavailable), as a limit that includes everything that appeared after it
+pub fn parse_since_file(value: &str) -> Result<TimeLimit, String> {
+    let path = Path::new(value);
+    let metadata = fs::metadata(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => format!("'{}' does not exist", path.display()),
+        _ => format!("cannot read '{}': {}", path.display(), e),
+    })?;
+    let (time, _) = entry_time(
+        &EntryInfo::new(path.to_path_buf(), &metadata),
+        TimeSource::Created,
+    )
+    .map_err(|e| e.to_string())?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_secs();
+
+    Ok(TimeLimit(now.saturating_sub(time).max(1)))
+}
+
+/// Parse a remote destination given as `[user@]host:path`, as `scp` takes it
+pub fn parse_remote(value: &str) -> Result<RemoteTarget, String> {
+    match value.split_once(':') {
+        Some((host, dir)) if !host.is_empty() && !host.ends_with('@') => Ok(RemoteTarget {
+            host: host.to_string(),
+            dir: dir.to_string(),
+        }),
+        _ => Err(format!(
+            "'{}' is not a remote destination, use user@host:/path",
+            value
+        )),
+    }
+}
+
+/// Parse a duration such as "45s", "90m", "2h", "3d" or "1w" into seconds.
+/// A bare number is taken as minutes, matching the original `time_limit` format.
+pub fn parse_duration(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", value))?;
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "s" => 1,
+        "" | "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        unit => return Err(format!("unknown duration unit '{}' in '{}'", unit, value)),
+    };
+
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("duration '{}' is too large", value))
+}
+
+/// Parse an optional size bound, treating zero as no bound
+pub fn parse_size_bound(
+    value: &Option<String>,
+    field: &str,
+    units: SizeUnits,
+) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+    match value {
+        Some(value) => match parse_size(value, units) {
+            Ok(0) => Ok(None),
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) => Err(format!("Invalid {} '{}': {}", field, value, e).into()),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Parse a human readable size such as "512B", "1.5KB" or "10MB", the inverse of `format_size`
+pub fn parse_size(value: &str, units: SizeUnits) -> Result<u64, String> {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let base = units.base();
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("expected a number, got '{}'", number))?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let unit_index = if unit.is_empty() {
+        0
+    } else {
+        UNITS
+            .iter()
+            .position(|u| *u == unit || u.trim_end_matches('B') == unit)
+            .ok_or_else(|| format!("unknown unit '{}'", unit))?
+    };
+
+    let bytes = number * base.powi(unit_index as i32);
+    // Casting would silently turn anything larger into u64::MAX
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("'{}' is too large", value));
+    }
+    Ok(bytes as u64)
+}
+
+/// Format a size like "1.2MB", in 1024-based or 1000-based `units`
+pub fn format_size(bytes: u64, units: SizeUnits) -> String {
+    let suffixes = units.suffixes();
+    let base = units.base();
+    let mut size = bytes as f64;
+    let mut unit_index = 0;
+
+    while size >= base && unit_index < suffixes.len() - 1 {
+        size /= base;
+        unit_index += 1;
+    }
+
+    if unit_index == 0 {
+        format!("{}B", bytes)
+    } else {
+        format!("{:.1}{}", size, suffixes[unit_index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for one test, removed again afterwards
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("m-test-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A tar entry with the name written as is, which `tar::Header::set_path` would refuse
+    fn raw_tar_entry(
+        builder: &mut tar::Builder<Vec<u8>>,
+        name: &str,
+        kind: tar::EntryType,
+        data: &[u8],
+    ) {
+        let mut header = tar::Header::new_gnu();
+        header.as_mut_bytes()[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_entry_type(kind);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
+
+    /// A config scanning only `source`, for files from the last day
+    fn scan_config(source: &Path) -> Config {
+        let mut config = default_config();
+        config.source_dir = vec![SourceDir::Path(source.to_string_lossy().to_string())];
+        config.time_limit = TimeLimit(24 * 60 * 60);
+        config
+    }
+
+    fn found_paths(config: &Config) -> Vec<PathBuf> {
+        let (files, _) = find_recent_files(config).unwrap();
+        assert!(
+            files
+                .windows(2)
+                .all(|pair| pair[0].created_timestamp >= pair[1].created_timestamp)
+        );
+        let mut paths: Vec<PathBuf> = files.into_iter().map(|file| file.path).collect();
+        paths.sort();
+        paths
+    }
+
+    /// Every file below `dir`, walked one directory after another
+    fn walk_serially(dir: &Path, files: &mut Vec<PathBuf>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_dir() {
+                walk_serially(&entry.path(), files);
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_scan_finds_what_a_serial_walk_finds() {
+        let dir = TempDir::new("parallel-scan");
+        fn generate(dir: &Path, depth: u32) {
+            for file in 0..4 {
+                fs::write(dir.join(format!("file{}.txt", file)), b"x").unwrap();
+            }
+            if depth < 4 {
+                for subdir in 0..3 {
+                    let subdir = dir.join(format!("dir{}", subdir));
+                    fs::create_dir(&subdir).unwrap();
+                    generate(&subdir, depth + 1);
+                }
+            }
+        }
+        generate(&dir.0, 0);
+
+        let mut expected = Vec::new();
+        walk_serially(&dir.0, &mut expected);
+        expected.sort();
+        assert_eq!(expected.len(), 4 * (1 + 3 + 9 + 27 + 81));
+
+        assert_eq!(found_paths(&scan_config(&dir.0)), expected);
+    }
+
+    #[test]
+    fn limit_keeps_the_newest_files() {
+        let dir = TempDir::new("limit");
+        let now = SystemTime::now();
+        let mut paths = Vec::new();
+        for index in 0..40u64 {
+            // Spread over several directories, so the cutoff is shared across threads
+            let subdir = dir.0.join(format!("dir{}", index % 4));
+            fs::create_dir_all(&subdir).unwrap();
+            let path = subdir.join(format!("file{}.txt", index));
+            fs::write(&path, b"x").unwrap();
+            let modified = now - Duration::from_secs(60 * (index * 7 % 40) + 60);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+            paths.push((modified, path));
+        }
+        paths.sort_by_key(|(modified, _)| Reverse(*modified));
+
+        let mut config = scan_config(&dir.0);
+        config.time_basis = TimeSource::Modified;
+        config.limit = 5;
+        let (files, _) = find_recent_files(&config).unwrap();
+        let found: Vec<_> = files.into_iter().map(|file| file.path).collect();
+        let newest: Vec<_> = paths.into_iter().take(5).map(|(_, path)| path).collect();
+        assert_eq!(found, newest);
+    }
+
+    #[test]
+    fn empty_source_dir_scans_the_current_directory() {
+        let dir = TempDir::new("current-dir");
+        fs::write(dir.0.join("here.txt"), b"x").unwrap();
+        // The only test that changes or relies on the current directory
+        let previous = std::env::current_dir().unwrap();
+        std::env::set_current_dir(&dir.0).unwrap();
+
+        let mut config = scan_config(&dir.0);
+        let mut names = Vec::new();
+        for source_dir in [vec![], vec![SourceDir::Path(".".to_string())]] {
+            config.source_dir = source_dir;
+            let (files, stats) = find_recent_files(&config).unwrap();
+            names.push(files.into_iter().map(|file| file.name).collect::<Vec<_>>());
+            assert_eq!(stats.dirs_walked, 1);
+        }
+        std::env::set_current_dir(previous).unwrap();
+        assert_eq!(names, [["here.txt"], ["here.txt"]]);
+    }
+
+    #[test]
+    fn pad_to_width_counts_wide_characters_twice() {
+        assert_eq!(pad_to_width("日本語", 8), "日本語  ");
+        assert_eq!(pad_to_width("abc", 8), "abc     ");
+        assert_eq!(pad_to_width("보고서.pdf", 4), "보고서.pdf");
+        assert_eq!(pad_to_width("日本語", 8).width(), 8);
+    }
+
+    #[test]
+    fn columns_line_up_with_wide_names() {
+        let dir = TempDir::new("wide-names");
+        for (name, size) in [("报告.pdf", 1), ("a.txt", 2048), ("データ集.csv", 30)] {
+            fs::write(dir.0.join(name), vec![0; size]).unwrap();
+        }
+        let mut config = scan_config(&dir.0);
+        config.display_format = "{name} | {size} | {ext}".to_string();
+
+        let (files, _) = find_recent_files(&config).unwrap();
+        let lines: Vec<String> = files.iter().map(|file| file.to_string()).collect();
+        assert_eq!(lines.len(), 3);
+        let columns = |line: &str| {
+            line.match_indices('|')
+                .map(|(at, _)| line[..at].width())
+                .collect::<Vec<_>>()
+        };
+        for line in &lines {
+            assert_eq!(columns(line), columns(&lines[0]), "{:?}", lines);
+        }
+        assert_eq!(columns(&lines[0])[0], "データ集.csv ".width());
+    }
+
+    fn file_info(path: &Path) -> FileInfo {
+        FileInfo {
+            path: path.to_path_buf(),
+            name: path.file_name().unwrap().to_string_lossy().to_string(),
+            size: fs::metadata(path).unwrap().len(),
+            is_dir: false,
+            created_time: String::new(),
+            created_timestamp: 0,
+            time_source: TimeSource::Modified,
+            time_fallback: false,
+            duplicates: 0,
+            source_root: path.parent().unwrap().to_path_buf(),
+            display_name: path.file_name().unwrap().to_string_lossy().to_string(),
+            warn: false,
+            layout: Arc::default(),
+        }
+    }
+
+    /// Options transferring into `target` without retries
+    fn transfer_options(target: &Path, mode: TransferMode) -> MoveOptions {
+        let mut options = MoveOptions::new(target.to_path_buf());
+        options.mode = mode;
+        options.retry = RetryPolicy {
+            retries: 0,
+            delay: Duration::ZERO,
+        };
+        options
+    }
+
+    #[test]
+    fn move_copies_across_filesystems() {
+        let dir = TempDir::new("cross-device");
+        let source = dir.0.join("report.pdf");
+        let target = dir.0.join("moved.pdf");
+        fs::write(&source, b"contents").unwrap();
+
+        let mut options = transfer_options(&dir.0, TransferMode::Move);
+        options.verify = true;
+        let crosses_devices = |_: &Path, _: &Path| Err(io::ErrorKind::CrossesDevices.into());
+        let removed = rename_or_copy(&file_info(&source), &target, &options, crosses_devices);
+        assert!(removed.unwrap());
+        assert!(!source.exists());
+        assert_eq!(fs::read(&target).unwrap(), b"contents");
+    }
+
+    #[test]
+    fn move_keeps_the_original_when_renaming_fails_otherwise() {
+        let dir = TempDir::new("rename-error");
+        let source = dir.0.join("report.pdf");
+        let target = dir.0.join("moved.pdf");
+        fs::write(&source, b"contents").unwrap();
+
+        let options = transfer_options(&dir.0, TransferMode::Move);
+        let denied = |_: &Path, _: &Path| Err(io::ErrorKind::PermissionDenied.into());
+        assert!(rename_or_copy(&file_info(&source), &target, &options, denied).is_err());
+        assert_eq!(fs::read(&source).unwrap(), b"contents");
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn hard_link_shares_the_file() {
+        let dir = TempDir::new("hard-link");
+        let source = dir.0.join("report.pdf");
+        let target_dir = dir.0.join("target");
+        fs::write(&source, b"contents").unwrap();
+
+        let options = transfer_options(&target_dir, TransferMode::HardLink);
+        let outcome = move_file(&file_info(&source), &options).unwrap();
+        assert_eq!(outcome, MoveOutcome::Moved);
+        let target = target_dir.join("report.pdf");
+        assert!(source.exists());
+        fs::write(&target, b"changed").unwrap();
+        assert_eq!(fs::read(&source).unwrap(), b"changed");
+    }
+
+    #[test]
+    fn directories_are_not_copied_into_themselves() {
+        let dir = TempDir::new("into-itself");
+        let source = dir.0.join("photos");
+        fs::create_dir(&source).unwrap();
+        let mut info = file_info(&source);
+        info.is_dir = true;
+
+        let options = transfer_options(&source.join("sorted"), TransferMode::Copy);
+        let error = move_file(&info, &options).unwrap_err();
+        assert!(error.to_string().starts_with("Cannot copy"));
+        let error = copy_to_free_path(&info, &source.join("sorted"), &options).unwrap_err();
+        assert!(error.to_string().contains("into itself"));
+        assert!(!source.join("sorted").exists());
+    }
+
+    // Creating symlinks on Windows needs extra rights
+    #[cfg(unix)]
+    #[test]
+    fn symlink_points_at_the_original() {
+        let dir = TempDir::new("symlink");
+        let source = dir.0.join("report.pdf");
+        let target_dir = dir.0.join("target");
+        fs::write(&source, b"contents").unwrap();
+
+        let options = transfer_options(&target_dir, TransferMode::Symlink);
+        let outcome = move_file(&file_info(&source), &options).unwrap();
+        assert_eq!(outcome, MoveOutcome::Moved);
+        let target = target_dir.join("report.pdf");
+        assert_eq!(
+            fs::read_link(&target).unwrap(),
+            std::path::absolute(&source).unwrap()
+        );
+        assert_eq!(fs::read(&target).unwrap(), b"contents");
+    }
+
+    fn limits() -> ExtractLimits {
+        ExtractLimits::for_archive(1 << 20)
+    }
+
+    #[test]
+    fn archive_entry_path_keeps_entries_inside() {
+        let destination = Path::new("/tmp/out");
+        assert_eq!(
+            archive_entry_path(destination, "dir/file.txt"),
+            Some(destination.join("dir").join("file.txt"))
+        );
+        assert_eq!(archive_entry_path(destination, "../x"), None);
+        assert_eq!(archive_entry_path(destination, "dir/../../x"), None);
+        assert_eq!(
+            archive_entry_path(destination, "/etc/passwd"),
+            Some(destination.join("etc").join("passwd"))
+        );
+        assert_eq!(archive_entry_path(destination, "C:\\Windows\\x"), None);
+        assert_eq!(archive_entry_path(destination, "./"), None);
+    }
+
+    #[test]
+    fn tar_round_trip() {
+        let dir = TempDir::new("tar-round-trip");
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "top.txt", &b"hello"[..])
+            .unwrap();
+        let long_name = format!("{}/nested.txt", "d".repeat(120));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, &long_name, &b"nested"[..])
+            .unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        let mut gzipped = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        gzipped.write_all(&archive).unwrap();
+        let gzipped = gzipped.finish().unwrap();
+
+        let extracted = extract_tar(GzDecoder::new(&gzipped[..]), &dir.0, limits()).unwrap();
+        assert_eq!((extracted.files, extracted.skipped), (2, 0));
+        assert_eq!(fs::read(dir.0.join("top.txt")).unwrap(), b"hello");
+        assert_eq!(fs::read(dir.0.join(long_name)).unwrap(), b"nested");
+    }
+
+    #[test]
+    fn extract_keeps_the_archive_unless_asked_to_delete_it() {
+        let dir = TempDir::new("extract-archive");
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "top.txt", &b"hello"[..])
+            .unwrap();
+        let archive = dir.0.join("photos.tar");
+        fs::write(&archive, builder.into_inner().unwrap()).unwrap();
+
+        let mut options = transfer_options(&dir.0.join("target"), TransferMode::Move);
+        options.extract = true;
+        move_file(&file_info(&archive), &options).unwrap();
+        assert_eq!(
+            fs::read(dir.0.join("target/photos/top.txt")).unwrap(),
+            b"hello"
+        );
+        assert!(archive.exists());
+
+        options.delete_archive = true;
+        move_file(&file_info(&archive), &options).unwrap();
+        assert!(dir.0.join("target/photos (1)/top.txt").exists());
+        assert!(!archive.exists());
+    }
+
+    #[test]
+    fn tar_skips_traversal_and_links() {
+        let dir = TempDir::new("tar-unsafe");
+        let destination = dir.0.join("out");
+        fs::create_dir(&destination).unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        raw_tar_entry(&mut builder, "../escaped", tar::EntryType::Regular, b"x");
+        raw_tar_entry(
+            &mut builder,
+            "a/../../escaped",
+            tar::EntryType::Regular,
+            b"x",
+        );
+        raw_tar_entry(&mut builder, "/absolute", tar::EntryType::Regular, b"x");
+
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder
+            .append_link(&mut header, "link", "/etc/passwd")
+            .unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        let extracted = extract_tar(&archive[..], &destination, limits()).unwrap();
+        assert_eq!((extracted.files, extracted.skipped), (1, 3));
+        assert!(!dir.0.join("escaped").exists());
+        assert!(fs::symlink_metadata(destination.join("link")).is_err());
+        // An absolute name is kept inside the destination
+        assert_eq!(fs::read(destination.join("absolute")).unwrap(), b"x");
+    }
+
+    fn write_zip(path: &Path, build: impl FnOnce(&mut zip::ZipWriter<fs::File>)) {
+        let mut writer = zip::ZipWriter::new(fs::File::create(path).unwrap());
+        build(&mut writer);
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn zip_round_trip() {
+        let dir = TempDir::new("zip-round-trip");
+        let archive = dir.0.join("archive.zip");
+        write_zip(&archive, |writer| {
+            let options = zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated);
+            writer.add_directory("docs/", options).unwrap();
+            writer.start_file("docs/readme.txt", options).unwrap();
+            writer.write_all(&b"read me ".repeat(100)).unwrap();
+            writer
+                .start_file(
+                    "stored.bin",
+                    options.compression_method(zip::CompressionMethod::Stored),
+                )
+                .unwrap();
+            writer.write_all(b"\x00\x01\x02").unwrap();
+        });
+
+        let destination = dir.0.join("out");
+        let extracted = extract_zip(&archive, &destination, limits()).unwrap();
+        assert_eq!((extracted.files, extracted.skipped), (2, 0));
+        assert_eq!(
+            fs::read(destination.join("docs").join("readme.txt")).unwrap(),
+            b"read me ".repeat(100)
+        );
+        assert_eq!(
+            fs::read(destination.join("stored.bin")).unwrap(),
+            b"\x00\x01\x02"
+        );
+    }
+
+    #[test]
+    fn zip_skips_traversal_and_links() {
+        let dir = TempDir::new("zip-unsafe");
+        let archive = dir.0.join("archive.zip");
+        write_zip(&archive, |writer| {
+            let options = zip::write::SimpleFileOptions::default();
+            for name in ["../escaped", "a/../../escaped", "C:/escaped", "kept.txt"] {
+                writer.start_file(name, options).unwrap();
+                writer.write_all(b"x").unwrap();
+            }
+            writer.add_symlink("link", "/etc/passwd", options).unwrap();
+        });
+
+        let destination = dir.0.join("out");
+        let extracted = extract_zip(&archive, &destination, limits()).unwrap();
+        assert_eq!((extracted.files, extracted.skipped), (1, 4));
+        assert!(!dir.0.join("escaped").exists());
+        assert!(fs::symlink_metadata(destination.join("link")).is_err());
+        assert_eq!(fs::read(destination.join("kept.txt")).unwrap(), b"x");
+    }
+
+    #[test]
+    fn extraction_stops_at_the_size_limit() {
+        let dir = TempDir::new("zip-bomb");
+        let archive = dir.0.join("bomb.zip");
+        write_zip(&archive, |writer| {
+            let options = zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated);
+            writer.start_file("zeros", options).unwrap();
+            writer.write_all(&vec![0; 1 << 20]).unwrap();
+        });
+
+        let limits = ExtractLimits {
+            entries: 10,
+            bytes: 1000,
+        };
+        let error = extract_zip(&archive, &dir.0.join("out"), limits).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn files_that_grow_while_looked_at_are_incomplete() {
+        let dir = TempDir::new("incomplete");
+        let path = dir.0.join("video.mp4");
+        fs::write(&path, b"first").unwrap();
+        let set_age = |seconds| {
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(seconds))
+                .unwrap()
+        };
+
+        set_age(3);
+        assert!(!is_incomplete(&path, "video.mp4"));
+        set_age(60);
+        assert!(!is_incomplete(&path, "video.mp4"));
+        assert!(is_incomplete(&path, "video.mp4.part"));
+
+        set_age(3);
+        let writer = thread::spawn({
+            let path = path.clone();
+            move || {
+                thread::sleep(Duration::from_millis(20));
+                fs::OpenOptions::new()
+                    .append(true)
+                    .open(path)
+                    .unwrap()
+                    .write_all(b"more")
+                    .unwrap();
+            }
+        });
+        assert!(is_incomplete(&path, "video.mp4"));
+        writer.join().unwrap();
+        assert!(is_incomplete(&path, "video.mp4"));
+    }
+
+    #[test]
+    fn sniffing_recognizes_every_signature() {
+        let mut pe = b"MZ".to_vec();
+        pe.resize(0x3c, 0);
+        pe.extend(0x40u32.to_le_bytes());
+        pe.extend(b"PE\0\0");
+        let mut tar = vec![0; 257];
+        tar.extend(b"ustar\x0000");
+        let cases: &[(&[u8], &str)] = &[
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", "image/png"),
+            (b"GIF87a\x01\0", "image/gif"),
+            (b"GIF89a\x01\0", "image/gif"),
+            (b"II*\0\x08\0\0\0", "image/tiff"),
+            (b"MM\0*\0\0\0\x08", "image/tiff"),
+            (b"%PDF-1.7\n", "application/pdf"),
+            (b"PK\x03\x04\x14\0", "application/zip"),
+            (b"7z\xbc\xaf\x27\x1c\0\x04", "application/x-7z-compressed"),
+            (b"Rar!\x1a\x07\x01\0", "application/vnd.rar"),
+            (b"\xfd7zXZ\0\0\x04", "application/x-xz"),
+            (b"\x28\xb5\x2f\xfd\x24\x05", "application/zstd"),
+            (b"SQLite format 3\0\x10\0", "application/vnd.sqlite3"),
+            (b"\x7fELF\x02\x01\x01", "application/x-executable"),
+            (b"OggS\0\x02", "audio/ogg"),
+            (b"fLaC\0\0\0\x22", "audio/flac"),
+            (b"\x1a\x45\xdf\xa3\x9f\x42", "video/webm"),
+            (b"\xff\xd8\xff\xe0\0\x10JFIF", "image/jpeg"),
+            (b"\x1f\x8b\x08\0\0\0\0\0", "application/gzip"),
+            (b"BZh91AY&SY", "application/x-bzip2"),
+            (b"BZh9\x17\x72\x45\x38\x50\x90", "application/x-bzip2"),
+            (b"ID3\x04\0\0\0\0\x02\x01", "audio/mpeg"),
+            (
+                b"\0\0\x01\0\x01\0\x10\x10\0\0\x01\0\x20\0\x68\x04\0\0\x16\0\0\0",
+                "image/x-icon",
+            ),
+            (&pe, "application/vnd.microsoft.portable-executable"),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", "image/webp"),
+            (b"RIFF\x24\0\0\0WAVEfmt ", "audio/wav"),
+            (b"RIFF\x24\0\0\0AVI LIST", "video/x-msvideo"),
+            (b"\0\0\0\x18ftypheic", "image/heic"),
+            (b"\0\0\0\x1cftypavif", "image/avif"),
+            (b"\0\0\0\x20ftypM4A ", "audio/mp4"),
+            (b"\0\0\0\x14ftypqt  ", "video/quicktime"),
+            (b"\0\0\0\x18ftypisom", "video/mp4"),
+            (&tar, "application/x-tar"),
+        ];
+        for (head, mime) in cases {
+            assert_eq!(sniff_mime(head), Some(*mime), "{:?}", head);
+        }
+
+        // Ordinary text and data that starts like one of the short signatures
+        let lookalikes: &[&[u8]] = &[
+            b"MZ is how the notes start",
+            b"BZh, not bzip2",
+            b"BZh9 and then text",
+            b"ID3 tags are covered below",
+            b"\0\0\x01\0\0\0\0\0\0\0\0\0",
+            b"\0\0\x01\0\x01\0\x10\x10\0\x07\x09\0",
+            b"\x1f\x8b\x07 is not deflate",
+            b"\xff\xd8\xff\x01",
+            b"RIFF",
+            b"plain text",
+            b"",
+        ];
+        for head in lookalikes {
+            assert_eq!(sniff_mime(head), None, "{:?}", head);
+        }
+    }
+
+    #[test]
+    fn content_type_falls_back_to_the_extension() {
+        let dir = TempDir::new("content-type");
+        let cases: &[(&str, &[u8], Option<&str>)] = &[
+            ("photo", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", Some("image/png")),
+            ("scan.txt", b"%PDF-1.7\n", Some("application/pdf")),
+            ("notes.txt", b"MZ is ordinary text", Some("text/plain")),
+            ("data.bin", b"BZh but not bzip2", None),
+            ("empty", b"", None),
+        ];
+        for (name, contents, expected) in cases {
+            let path = dir.0.join(name);
+            fs::write(&path, contents).unwrap();
+            assert_eq!(content_type(&path), *expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn time_limits_too_long_to_count_in_seconds_are_rejected() {
+        assert_eq!(
+            TimeLimit::from_minutes(u64::MAX / 60),
+            Some(TimeLimit(u64::MAX / 60 * 60))
+        );
+        assert_eq!(TimeLimit::from_minutes(u64::MAX / 60 + 1), None);
+
+        let too_long = (u64::MAX / 60 + 1).to_string();
+        let error = serde_json::from_str::<TimeLimit>(&too_long).unwrap_err();
+        assert!(error.to_string().contains("is too long"));
+        let error =
+            toml::from_str::<Config>(&format!("time_limit = {}\nblack_list = []", i64::MAX))
+                .unwrap_err();
+        assert!(error.to_string().contains("is too long"));
+    }
+
+    #[test]
+    fn applying_colors_twice_keeps_the_first_choice() {
+        ColorMode::Never.apply();
+        ColorMode::Always.apply();
+        assert_eq!(styled("plain".yellow()), "plain");
+    }
+
+    #[test]
+    fn list_entries_groups_files_by_age() {
+        let dir = TempDir::new("list-entries");
+        let now = chrono::Local::now().timestamp() as u64;
+        let files: Vec<FileInfo> = [
+            ("new.txt", 60),
+            ("hour.txt", 30 * 60),
+            ("old.txt", 3 * 86400),
+        ]
+        .into_iter()
+        .map(|(name, age)| {
+            let path = dir.0.join(name);
+            fs::write(&path, b"x").unwrap();
+            let mut info = file_info(&path);
+            info.created_timestamp = now - age;
+            info
+        })
+        .collect();
+        let names = |entries: &[ListEntry]| -> Vec<String> {
+            entries.iter().map(ToString::to_string).collect()
+        };
+        let mut config = default_config();
+        config.ascii = true;
+
+        let entries = list_entries(files.clone(), &config);
+        assert!(
+            entries
+                .iter()
+                .all(|entry| matches!(entry, ListEntry::File(_)))
+        );
+
+        config.group_by_age = true;
+        let entries = list_entries(files.clone(), &config);
+        let labels: Vec<&str> = entries.iter().map(ListEntry::display_name).collect();
+        assert_eq!(
+            labels,
+            [
+                "Last 5 minutes",
+                "new.txt",
+                "Last hour",
+                "hour.txt",
+                "Earlier",
+                "old.txt"
+            ]
+        );
+        assert_eq!(names(&entries)[0], "-- Last 5 minutes --");
+
+        config.reverse = true;
+        config.ascii = false;
+        let entries = list_entries(files.into_iter().rev().collect(), &config);
+        let labels: Vec<&str> = entries.iter().map(ListEntry::display_name).collect();
+        assert_eq!(
+            labels,
+            [
+                "Earlier",
+                "old.txt",
+                "Last hour",
+                "hour.txt",
+                "Last 5 minutes",
+                "new.txt"
+            ]
+        );
+        assert_eq!(names(&entries)[0], "── Earlier ──");
+
+        assert!(list_entries(Vec::new(), &config).is_empty());
+    }
+
+    #[test]
+    fn image_dimensions_of_each_format() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend(640u32.to_be_bytes());
+        png.extend(480u32.to_be_bytes());
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend(32u16.to_le_bytes());
+        gif.extend(16u16.to_le_bytes());
+        let mut bmp = b"BM".to_vec();
+        bmp.resize(18, 0);
+        bmp.extend(100i32.to_le_bytes());
+        // Top-down bitmaps have a negative height
+        bmp.extend((-50i32).to_le_bytes());
+        let mut jpeg = vec![0xff, 0xd8];
+        // An APP0 segment before the frame
+        jpeg.extend([0xff, 0xe0, 0x00, 0x04, 0x00, 0x00]);
+        jpeg.extend([0xff, 0xc0, 0x00, 0x11, 0x08]);
+        jpeg.extend(200u16.to_be_bytes());
+        jpeg.extend(300u16.to_be_bytes());
+
+        let cases = [
+            (&png[..], Some(("PNG", 640, 480))),
+            (&gif[..], Some(("GIF", 32, 16))),
+            (&bmp[..], Some(("BMP", 100, 50))),
+            (&jpeg[..], Some(("JPEG", 300, 200))),
+            (&png[..18], None),
+            (&[0xff, 0xd8, 0x00, 0x00, 0x00, 0x00][..], None),
+            (&b"plain text"[..], None),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(image_dimensions(header), expected, "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn preview_describes_the_contents() {
+        let dir = TempDir::new("preview");
+        let write = |name: &str, contents: &[u8]| {
+            let path = dir.0.join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        };
+
+        let lines: String = (1..=12).map(|n| format!("line {}\n", n)).collect();
+        let text = preview_file(&write("notes.txt", lines.as_bytes())).unwrap();
+        assert_eq!(text.lines().count(), 10);
+        assert!(text.starts_with("line 1\nline 2"));
+        assert_eq!(
+            preview_file(&write("empty.txt", b"")).unwrap(),
+            "(empty file)"
+        );
+
+        // A multibyte character cut off by the read limit is still text
+        let mut long = "é".repeat(32 * 1024).into_bytes();
+        long.push(0xc3);
+        long.extend("é".repeat(100).into_bytes());
+        let cut = preview_file(&write("long.txt", &long)).unwrap();
+        assert!(cut.starts_with("éé"));
+
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend(2u32.to_be_bytes());
+        png.extend(3u32.to_be_bytes());
+        assert_eq!(
+            preview_file(&write("dot.png", &png)).unwrap(),
+            "PNG image, 2x3 pixels"
+        );
+
+        let binary = preview_file(&write("blob.bin", &[0, 1, 2, 0xff])).unwrap();
+        assert_eq!(
+            binary.trim_end(),
+            "00000000  00 01 02 ff                                      ...."
+        );
+
+        let folder = dir.0.join("folder");
+        fs::create_dir(&folder).unwrap();
+        assert_eq!(preview_file(&folder).unwrap(), "(empty directory)");
+        fs::write(folder.join("b.txt"), b"").unwrap();
+        fs::write(folder.join("a.txt"), b"").unwrap();
+        assert_eq!(
+            preview_file(&folder).unwrap(),
+            "Directory with 2 entries\na.txt\nb.txt"
+        );
+    }
+
+    #[test]
+    fn sizes_round_trip() {
+        let cases = [
+            (0, SizeUnits::Binary, "0B"),
+            (512, SizeUnits::Binary, "512B"),
+            (1536, SizeUnits::Binary, "1.5KB"),
+            (1 << 20, SizeUnits::Binary, "1.0MB"),
+            (5 << 40, SizeUnits::Binary, "5.0TB"),
+            (1500, SizeUnits::Si, "1.5kB"),
+            (2_000_000_000, SizeUnits::Si, "2.0GB"),
+        ];
+        for (bytes, units, text) in cases {
+            assert_eq!(format_size(bytes, units), text);
+            assert_eq!(parse_size(text, units), Ok(bytes), "{}", text);
+        }
+    }
+
+    #[test]
+    fn parse_size_accepts_and_rejects() {
+        let cases = [
+            ("10", Ok(10)),
+            (" 2 kb ", Ok(2048)),
+            ("10M", Ok(10 << 20)),
+            ("0.5GB", Ok(1 << 29)),
+            ("0", Ok(0)),
+            ("", Err("expected a number, got ''")),
+            ("MB", Err("expected a number, got ''")),
+            ("1.2.3KB", Err("expected a number, got '1.2.3'")),
+            ("-1KB", Err("expected a number, got ''")),
+            ("10 XB", Err("unknown unit 'XB'")),
+            ("5 bytes", Err("unknown unit 'BYTES'")),
+            ("99999999TB", Err("'99999999TB' is too large")),
+        ];
+        for (text, expected) in cases {
+            let expected = expected.map_err(str::to_string);
+            assert_eq!(parse_size(text, SizeUnits::Binary), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn zero_size_bounds_mean_no_bound() {
+        let bound =
+            |value: &str| parse_size_bound(&Some(value.to_string()), "min_size", SizeUnits::Si);
+        assert_eq!(bound("0").unwrap(), None);
+        assert_eq!(bound("1kB").unwrap(), Some(1000));
+        assert_eq!(
+            bound("1 parsec").unwrap_err().to_string(),
+            "Invalid min_size '1 parsec': unknown unit 'PARSEC'"
+        );
+        assert_eq!(
+            parse_size_bound(&None, "min_size", SizeUnits::Si).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        let cases = [
+            ("45s", Ok(45)),
+            ("30", Ok(30 * 60)),
+            ("30m", Ok(30 * 60)),
+            (" 2H ", Ok(2 * 3600)),
+            ("1d", Ok(86400)),
+            ("2 w", Ok(2 * 7 * 86400)),
+            ("0", Ok(0)),
+            ("", Err("invalid duration ''")),
+            ("h", Err("invalid duration 'h'")),
+            ("1.5h", Err("unknown duration unit '.5h' in '1.5h'")),
+            ("3y", Err("unknown duration unit 'y' in '3y'")),
+            (
+                "99999999999999999999",
+                Err("invalid duration '99999999999999999999'"),
+            ),
+            (
+                "99999999999999999w",
+                Err("duration '99999999999999999w' is too large"),
+            ),
+        ];
+        for (text, expected) in cases {
+            let expected = expected.map_err(str::to_string);
+            assert_eq!(parse_duration(text), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn time_limit_serde() {
+        let cases = [
+            ("30", Some(30 * 60)),
+            ("\"2h\"", Some(2 * 3600)),
+            ("\"90s\"", Some(90)),
+            ("0", Some(0)),
+            ("-5", None),
+            ("\"soon\"", None),
+            ("1.5", None),
+            (&format!("{}", u64::MAX / 60 + 1), None),
+        ];
+        for (json, seconds) in cases {
+            let parsed = serde_json::from_str::<TimeLimit>(json).ok();
+            assert_eq!(parsed.map(TimeLimit::seconds), seconds, "{}", json);
+        }
+        assert_eq!(
+            toml::from_str::<BTreeMap<String, TimeLimit>>("limit = 15").unwrap()["limit"],
+            TimeLimit(15 * 60)
+        );
+        assert!(toml::from_str::<BTreeMap<String, TimeLimit>>("limit = -15").is_err());
+
+        // Whole minutes are written as a number, anything else as seconds
+        for (seconds, json) in [(1800, "30"), (90, "\"90s\""), (0, "0")] {
+            let written = serde_json::to_string(&TimeLimit(seconds)).unwrap();
+            assert_eq!(written, json);
+            assert_eq!(
+                serde_json::from_str::<TimeLimit>(&written).unwrap(),
+                TimeLimit(seconds)
+            );
+        }
+        assert_eq!(TimeLimit::from_minutes(u64::MAX), None);
+        assert_eq!(TimeLimit(7200).to_string(), "2 hours");
+        assert_eq!(TimeLimit(90).to_string(), "90 seconds");
+    }
+
+    #[test]
+    fn pattern_set_matching() {
+        let patterns = |list: &[&str]| list.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let cases: &[(&[&str], bool, &str, bool)] = &[
+            // Plain patterns are substrings of the name
+            (&["tmp"], false, "/d/file.tmp", true),
+            (&["tmp"], false, "/tmp/file.txt", false),
+            (&["TMP"], false, "/d/file.tmp", false),
+            (&["TMP"], true, "/d/File.tmp", true),
+            // Globs without a slash match the whole name
+            (&["*.part"], false, "/d/video.mp4.part", true),
+            (&["*.part"], false, "/d/video.partial", false),
+            (&["*.PART"], true, "/d/video.part", true),
+            (&["report-[0-9].pdf"], false, "/d/report-7.pdf", true),
+            (&["{a,b}.txt"], false, "/d/c.txt", false),
+            // Globs with a slash match the full path
+            (
+                &["**/node_modules/**"],
+                false,
+                "/w/app/node_modules/x.js",
+                true,
+            ),
+            (&["**/node_modules/**"], false, "/w/app/src/x.js", false),
+            (&["tmp", "*.log"], false, "/d/build.log", true),
+            (&[], false, "/d/anything", false),
+        ];
+        for &(list, ignore_case, path, expected) in cases {
+            let set = PatternSet::new(&patterns(list), ignore_case).unwrap();
+            let path = Path::new(path);
+            let name = path.file_name().unwrap().to_str().unwrap();
+            assert_eq!(
+                set.is_match(name, path),
+                expected,
+                "{:?} on {}",
+                list,
+                path.display()
+            );
+        }
+        assert!(PatternSet::new(&patterns(&["[unclosed"]), false).is_err());
+    }
+
+    #[test]
+    fn unique_target_path_numbers_taken_names() {
+        let dir = TempDir::new("unique-path");
+        let cases = [
+            ("report.pdf", &[][..], "report (1).pdf"),
+            (
+                "report.pdf",
+                &["report (1).pdf", "report (2).pdf"][..],
+                "report (3).pdf",
+            ),
+            ("README", &[][..], "README (1)"),
+            (".bashrc", &[][..], ".bashrc (1)"),
+            ("photos.tar.gz", &[][..], "photos.tar (1).gz"),
+            ("日本語.txt", &[][..], "日本語 (1).txt"),
+        ];
+        for (name, taken, expected) in cases {
+            for taken in taken {
+                fs::write(dir.0.join(taken), b"").unwrap();
+            }
+            assert_eq!(unique_target_path(&dir.0, name), dir.0.join(expected));
+        }
+
+        // A dangling link still takes up the name
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.0.join("gone"), dir.0.join("link (1)")).unwrap();
+            assert_eq!(unique_target_path(&dir.0, "link"), dir.0.join("link (2)"));
+        }
+    }
+
+    #[test]
+    fn expand_path_cases() {
+        let home = dirs::home_dir().unwrap().to_string_lossy().to_string();
+        let path = std::env::var("PATH").unwrap();
+        let sep = std::path::MAIN_SEPARATOR;
+        let cases = [
+            ("~", home.clone()),
+            ("~/Downloads", format!("{}{}Downloads", home, sep)),
+            ("~user/x", "~user/x".to_string()),
+            ("a/~/b", "a/~/b".to_string()),
+            ("$PATH/x", format!("{}/x", path)),
+            ("${PATH}x", format!("{}x", path)),
+            ("$PATH_M_UNSET/x", "$PATH_M_UNSET/x".to_string()),
+            ("${M_TEST_UNSET}", "${M_TEST_UNSET}".to_string()),
+            ("cost $ and $", "cost $ and $".to_string()),
+            ("${unclosed", "${unclosed".to_string()),
+            ("", "".to_string()),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(expand_path(input), PathBuf::from(&expected), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn dedup_files_keeps_the_newest_of_each_group() {
+        let dir = TempDir::new("dedup");
+        fs::create_dir_all(dir.0.join("a")).unwrap();
+        fs::create_dir_all(dir.0.join("b")).unwrap();
+        let files: Vec<FileInfo> = [
+            ("a/x.txt", "same", 10),
+            ("b/x.txt", "same", 20),
+            ("b/y.txt", "same", 5),
+            ("c.txt", "diff", 30),
+            ("z.txt", "unique size", 1),
+        ]
+        .into_iter()
+        .map(|(name, contents, created)| {
+            let path = dir.0.join(name);
+            fs::write(&path, contents).unwrap();
+            let mut info = file_info(&path);
+            info.created_timestamp = created;
+            info
+        })
+        .collect();
+        let kept = |dedup_by| -> Vec<(String, usize)> {
+            dedup_files(files.clone(), dedup_by)
+                .into_iter()
+                .map(|file| {
+                    let path = file.path.strip_prefix(&dir.0).unwrap();
+                    (path.to_string_lossy().replace('\\', "/"), file.duplicates)
+                })
+                .collect()
+        };
+        let owned = |list: &[(&str, usize)]| -> Vec<(String, usize)> {
+            list.iter()
+                .map(|&(name, count)| (name.to_string(), count))
+                .collect()
+        };
+
+        assert_eq!(
+            kept(DedupBy::None),
+            owned(&[
+                ("a/x.txt", 0),
+                ("b/x.txt", 0),
+                ("b/y.txt", 0),
+                ("c.txt", 0),
+                ("z.txt", 0)
+            ])
+        );
+        assert_eq!(
+            kept(DedupBy::Name),
+            owned(&[("b/x.txt", 1), ("b/y.txt", 0), ("c.txt", 0), ("z.txt", 0)])
+        );
+        assert_eq!(
+            kept(DedupBy::Content),
+            owned(&[("b/x.txt", 2), ("c.txt", 0), ("z.txt", 0)])
+        );
+        assert!(dedup_files(Vec::new(), DedupBy::Content).is_empty());
+    }
+
+    #[test]
+    fn source_dir_is_a_path_or_an_object() {
+        let mut config = default_config();
+        config.time_limit = TimeLimit(3600);
+        config.black_list = vec!["tmp".to_string()];
+        config.extensions = vec!["pdf".to_string()];
+
+        // The path, then the time limit, black list and extensions in effect
+        let cases = [
+            (
+                r#""~/Downloads""#,
+                "~/Downloads",
+                3600,
+                vec!["tmp"],
+                vec!["pdf"],
+            ),
+            (r#"{"path": "/in"}"#, "/in", 3600, vec!["tmp"], vec!["pdf"]),
+            (
+                r#"{"path": "/in", "time_limit": "2h", "black_list": [], "extensions": ["png"]}"#,
+                "/in",
+                7200,
+                vec![],
+                vec!["png"],
+            ),
+            (
+                r#"{"path": "/in", "time_limit": 5}"#,
+                "/in",
+                300,
+                vec!["tmp"],
+                vec!["pdf"],
+            ),
+        ];
+        for (json, path, seconds, black_list, extensions) in cases {
+            let source: SourceDir = serde_json::from_str(json).unwrap();
+            assert_eq!(source.path(), path, "{}", json);
+            assert_eq!(source.time_limit(&config).seconds(), seconds, "{}", json);
+            assert_eq!(source.black_list(&config), black_list, "{}", json);
+            assert_eq!(source.extensions(&config), extensions, "{}", json);
+        }
+
+        for invalid in [
+            "42",
+            r#"{"time_limit": "2h"}"#,
+            r#"{"path": "/in", "time_limit": "soon"}"#,
+            r#"["/in"]"#,
+        ] {
+            assert!(
+                serde_json::from_str::<SourceDir>(invalid).is_err(),
+                "{}",
+                invalid
+            );
+        }
+
+        // Written back the way it was given, leaving out what is not overridden
+        let sources: Vec<SourceDir> =
+            serde_json::from_str(r#"["/a", {"path": "/b", "extensions": ["png"]}]"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&sources).unwrap(),
+            r#"["/a",{"path":"/b","extensions":["png"]}]"#
+        );
+        let from_toml: BTreeMap<String, Vec<SourceDir>> =
+            toml::from_str(r#"source_dir = ["/a", { path = "/b", time_limit = 10 }]"#).unwrap();
+        assert_eq!(
+            from_toml["source_dir"][1].time_limit(&config).seconds(),
+            600
+        );
+    }
+
+    #[test]
+    fn parse_display_format_cases() {
+        use FormatPart::{Column, Text};
+        let cases = [
+            (
+                "{time} {size} {name}",
+                Ok(vec![
+                    Column("time"),
+                    Text(" "),
+                    Column("size"),
+                    Text(" "),
+                    Column("name"),
+                ]),
+            ),
+            (
+                "[{ext}] {path}!",
+                Ok(vec![
+                    Text("["),
+                    Column("ext"),
+                    Text("] "),
+                    Column("path"),
+                    Text("!"),
+                ]),
+            ),
+            ("{name}{size}", Ok(vec![Column("name"), Column("size")])),
+            ("no columns }", Ok(vec![Text("no columns }")])),
+            ("", Ok(vec![])),
+            ("{name", Err("'{name' has a '{' without a closing '}'")),
+            (
+                "{}",
+                Err("'{}' is not one of {time}, {size}, {name}, {path} or {ext}"),
+            ),
+            (
+                "{Name}",
+                Err("'{Name}' is not one of {time}, {size}, {name}, {path} or {ext}"),
+            ),
+            (
+                "{na{me}",
+                Err("'{na{me}' is not one of {time}, {size}, {name}, {path} or {ext}"),
+            ),
+        ];
+        for (format, expected) in cases {
+            let expected = expected.map_err(str::to_string);
+            assert_eq!(parse_display_format(format), expected, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn validate_config_collects_every_problem() {
+        let absolute = std::env::temp_dir().to_string_lossy().to_string();
+        let source = |path: &str, time_limit| SourceDir::Detailed {
+            path: path.to_string(),
+            time_limit,
+            black_list: None,
+            extensions: None,
+        };
+        let strings = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        // Each change on its own, and the start of the problem it causes
+        type Change<'a> = Box<dyn Fn(&mut Config) + 'a>;
+        let cases: Vec<(Change, &str)> = vec![
+            (
+                Box::new(|c| c.source_dir = vec![SourceDir::Path("relative".to_string())]),
+                "source_dir: 'relative' must be an absolute path",
+            ),
+            (
+                Box::new(move |c| c.source_dir = vec![source(&absolute, Some(TimeLimit(0)))]),
+                "source_dir: the time_limit of 0 for",
+            ),
+            (
+                Box::new(|c| c.source_list_file = Some("/nonexistent/m-sources.txt".to_string())),
+                "source_list_file: Failed to read",
+            ),
+            (
+                Box::new(|c| c.time_limit = TimeLimit(0)),
+                "time_limit: 0 includes no files",
+            ),
+            (
+                Box::new(|c| c.date_subdir = Some("%Q".to_string())),
+                "date_subdir: '%Q' is not a valid date format",
+            ),
+            (
+                Box::new(|c| c.date_subdir = Some(" ".to_string())),
+                "date_subdir: ' ' is not a valid date format",
+            ),
+            (
+                Box::new(|c| c.date_subdir = Some("../%Y".to_string())),
+                "date_subdir: '../%Y' must stay inside target_dir",
+            ),
+            (
+                Box::new(|c| {
+                    c.type_routing = BTreeMap::from([("".to_string(), strings(&["pdf"]))])
+                }),
+                "type_routing: '' must be a directory inside target_dir",
+            ),
+            (
+                Box::new(|c| {
+                    c.type_routing = BTreeMap::from([
+                        ("Docs".to_string(), strings(&["pdf"])),
+                        ("Papers".to_string(), strings(&[".PDF"])),
+                    ])
+                }),
+                "type_routing: 'pdf' is routed to both 'Docs' and 'Papers'",
+            ),
+            (
+                Box::new(|c| c.mime_allow = strings(&["image"])),
+                "mime_allow: 'image' is not a content type",
+            ),
+            (
+                Box::new(|c| c.mime_deny = strings(&["/png"])),
+                "mime_deny: '/png' is not a content type",
+            ),
+            (
+                Box::new(|c| c.display_format = "{when}".to_string()),
+                "display_format: '{when}' is not one of",
+            ),
+        ];
+
+        let valid = || {
+            let mut config = default_config();
+            config.source_dir = vec![SourceDir::Path(".".to_string())];
+            config
+        };
+        assert_eq!(validate_config(&valid()), Ok(()));
+        let mut all = valid();
+        for (change, expected) in &cases {
+            let mut config = valid();
+            change(&mut config);
+            let problems = validate_config(&config).unwrap_err();
+            assert_eq!(problems.len(), 1, "{:?}", problems);
+            assert!(problems[0].starts_with(expected), "{:?}", problems);
+            change(&mut all);
+        }
+
+        // Every problem is reported, not just the first. Later changes to the same
+        // field replace earlier ones, so each field shows up once
+        let problems = validate_config(&all).unwrap_err();
+        let fields: Vec<&str> = problems
+            .iter()
+            .map(|problem| problem.split(':').next().unwrap())
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                "source_list_file",
+                "source_dir",
+                "time_limit",
+                "date_subdir",
+                "type_routing",
+                "mime_allow",
+                "mime_deny",
+                "display_format"
+            ]
+        );
+    }
+}
@@ -1,296 +1,1901 @@
+use clap::{CommandFactory, Parser, Subcommand};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
 use crossterm::style::Stylize;
-use inquire::{Confirm, Select};
-use serde::{Deserialize, Serialize};
-use serde_json;
-use std::fmt;
+use crossterm::{cursor, execute, queue, terminal};
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use inquire::autocompletion::Replacement;
+use inquire::ui::RenderConfig;
+use inquire::validator::Validation;
+use inquire::{Autocomplete, Confirm, CustomUserError, InquireError, MultiSelect, Select, Text};
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::process;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-#[derive(Debug, Deserialize, Serialize)]
-struct Config {
-    source_dir: Vec<String>,
-    time_limit: u64,
-    black_list: Vec<String>,
-}
-
-#[derive(Debug, Clone)]
-struct FileInfo {
-    path: PathBuf,
-    name: String,
-    size: u64,
-    created_time: String,
-    created_timestamp: u64,
-    time_width: usize,
-    size_width: usize,
-}
-
-impl fmt::Display for FileInfo {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{:<time_width$} {:<size_width$} {}",
-            self.created_time,
-            format_size(self.size),
-            self.name,
-            time_width = self.time_width,
-            size_width = self.size_width
-        )
-    }
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{OnceLock, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthStr;
+
+use m::{
+    ColorMode, Config, ConfigFormat, FileInfo, ListEntry, MoveOptions, MoveOutcome, OnConflict,
+    RemoteTarget, RetryPolicy, ScanStats, SizeUnits, SortBy, SourceDir, TimeLimit, TransferMode,
+    config_dir, default_config, describe_dir, expand_path, find_recent_files, format_size,
+    last_destination, list_entries, missing_source_dirs, move_atomically, move_file, pad_to_width,
+    parse_remote, parse_since_file, parse_size_bound, parse_time_limit, preview_file, read_config,
+    styled, undo_last_move, upload_file, validate_config,
+};
+
+// Exit codes, so scripts can tell "nothing to do" apart from real errors
+const EXIT_CONFIG_ERROR: i32 = 1;
+const EXIT_SCAN_ERROR: i32 = 2;
+const EXIT_NO_FILES: i32 = 3;
+const EXIT_CANCELED: i32 = 4;
+const EXIT_MOVE_FAILED: i32 = 5;
+
+/// Set from `--quiet` or the `quiet` setting, which the library gets passed instead
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Set from `--ascii`, `M_ASCII` or the `ascii` setting, for the list headers and hints
+static ASCII: AtomicBool = AtomicBool::new(false);
+
+/// Print informational output, which `--quiet` suppresses. Errors, the `--json`
+/// and `--list` output, dry runs and previews are printed regardless.
+macro_rules! report {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Print a warning or another notice on stderr, which `--quiet` suppresses
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Move newly created files into the current directory
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Only include files whose name contains this, ignoring case, overriding `name_contains`
+    #[arg(value_name = "NAME")]
+    name: Option<String>,
+
+    /// Read the configuration from this file instead of ~/.config/m/m.{toml,yaml,json}
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+
+    /// Format of a newly created configuration file
+    #[arg(long, value_name = "FORMAT", global = true, default_value = "json")]
+    format: ConfigFormat,
+
+    /// Copy the file instead of moving it, leaving the original in place
+    #[arg(short, long, conflicts_with_all = ["link", "symlink"])]
+    copy: bool,
+
+    /// Create a hard link in the target directory, leaving the original in place
+    #[arg(long, conflicts_with = "symlink")]
+    link: bool,
+
+    /// Create a symbolic link in the target directory, leaving the original in place
+    #[arg(long)]
+    symlink: bool,
+
+    /// Delete the selected files after confirming, to the trash with --trash, instead of moving them
+    #[arg(
+        long,
+        conflicts_with_all = ["copy", "link", "symlink", "to", "remote", "extract", "leave_link", "clip", "open"]
+    )]
+    delete: bool,
+
+    /// Send the original to the trash after copying instead of deleting it
+    #[arg(long, conflicts_with_all = ["copy", "link", "symlink"])]
+    trash: bool,
+
+    /// Select and move several files in one run
+    #[arg(short, long)]
+    multi: bool,
+
+    /// With --multi, copy and verify every file before removing any original, and undo the
+    /// copies if one fails, so the batch is moved completely or not at all
+    #[arg(
+        long,
+        requires = "multi",
+        conflicts_with_all = ["copy", "link", "symlink", "delete", "extract", "leave_link"]
+    )]
+    atomic: bool,
+
+    /// Only include files from this long ago (e.g. 90m, 2h, 3d), overriding `time_limit`
+    #[arg(long, value_name = "DURATION", value_parser = parse_time_limit)]
+    since: Option<TimeLimit>,
+
+    /// Only include files created after this file, e.g. a marker made with `touch`
+    #[arg(
+        long,
+        value_name = "PATH",
+        value_parser = parse_since_file,
+        conflicts_with = "since"
+    )]
+    since_file: Option<TimeLimit>,
+
+    /// Include files of any age instead of only recent ones, best combined with --limit
+    #[arg(long, conflicts_with_all = ["since", "since_file"])]
+    all: bool,
+
+    /// Directory to move files into, overriding `target_dir` from the config.
+    /// Given more than once, each directory gets a copy
+    #[arg(long, value_name = "PATH")]
+    to: Vec<String>,
+
+    /// Only include files with these extensions (comma separated)
+    #[arg(long, value_name = "EXT", value_delimiter = ',')]
+    ext: Vec<String>,
+
+    /// Only include files at least this large (e.g. 10MB)
+    #[arg(long, value_name = "SIZE")]
+    min_size: Option<String>,
+
+    /// Only include files at most this large (e.g. 1GB)
+    #[arg(long, value_name = "SIZE")]
+    max_size: Option<String>,
+
+    /// Only include empty files, to clean up placeholders
+    #[arg(long)]
+    only_empty: bool,
+
+    /// Maximum directory depth to recurse into, 0 scans only the top level
+    #[arg(long, value_name = "N")]
+    depth: Option<u32>,
+
+    /// Also scan the directories listed in this file, one per line, overriding
+    /// source_list_file from the config
+    #[arg(long, value_name = "FILE")]
+    source_file: Option<String>,
+
+    /// Only scan the top level of each source directory, like --depth 0
+    #[arg(long)]
+    no_recursive: bool,
+
+    /// Show what would be moved without touching the filesystem
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Order of the file list, overriding `sort_by` from the config
+    #[arg(long, value_enum)]
+    sort: Option<SortBy>,
+
+    /// Reverse the sort order
+    #[arg(short, long)]
+    reverse: bool,
+
+    /// Show at most this many files, after sorting, 0 shows all
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+
+    /// List the oldest files of the time window first, for cleaning up
+    #[arg(long, conflicts_with = "sort")]
+    cleanup: bool,
+
+    /// Highlight files at least this old (e.g. 25d), overriding `warn_age`
+    #[arg(long, value_name = "DURATION", value_parser = parse_time_limit)]
+    warn_age: Option<TimeLimit>,
+
+    /// Print the candidate files as JSON and exit without prompting
+    #[arg(long)]
+    json: bool,
+
+    /// Print the candidate files as they appear in the selector and exit without prompting
+    #[arg(short, long, conflicts_with = "json")]
+    list: bool,
+
+    /// Overwrite existing files, and with --multi move the selection, without asking
+    #[arg(short, long, conflicts_with = "no_clobber")]
+    force: bool,
+
+    /// Skip files that already exist in the target directory without asking
+    #[arg(short = 'n', long)]
+    no_clobber: bool,
+
+    /// What to do when the target file already exists, instead of asking
+    #[arg(long, value_name = "ACTION", conflicts_with_all = ["force", "no_clobber"])]
+    on_conflict: Option<OnConflict>,
+
+    /// Preview the selected file and confirm before moving it
+    #[arg(short, long)]
+    preview: bool,
+
+    /// Open the target directory in the file manager after moving
+    #[arg(long)]
+    open: bool,
+
+    /// Copy the selected file's path to the clipboard instead of moving it
+    #[arg(long, conflicts_with = "multi")]
+    clip: bool,
+
+    /// Move the Nth file of the list (1-based) without prompting
+    #[arg(long, value_name = "N", conflicts_with_all = ["multi", "preview"])]
+    pick: Option<usize>,
+
+    /// Move the most recent file without prompting
+    #[arg(long, conflicts_with_all = ["multi", "preview", "pick"])]
+    newest: bool,
+
+    /// Upload the selected file to user@host:/path over SFTP instead of moving it locally
+    #[arg(
+        long,
+        value_name = "DEST",
+        value_parser = parse_remote,
+        conflicts_with_all = ["to", "link", "symlink", "multi", "watch", "clip", "open"]
+    )]
+    remote: Option<RemoteTarget>,
+
+    /// Leave a symlink to the moved file at its original location
+    #[arg(long, conflicts_with_all = ["copy", "link", "symlink", "extract", "remote", "clip"])]
+    leave_link: bool,
+
+    /// Extract a selected zip or tar archive into a directory in the target directory
+    /// instead of moving it, keeping the archive
+    #[arg(long, conflicts_with_all = ["link", "symlink", "remote", "clip"])]
+    extract: bool,
+
+    /// With --extract, delete the archive afterwards, to the trash with --trash
+    #[arg(long, requires = "extract", conflicts_with = "copy")]
+    delete_archive: bool,
+
+    /// Compare copies with the original by hash before deleting the original
+    #[arg(long)]
+    verify: bool,
+
+    /// With --multi, ask before moving more than this in total (e.g. 4GB)
+    #[arg(long, value_name = "SIZE", requires = "multi")]
+    budget: Option<String>,
+
+    /// Only print errors and the requested output, such as --json or --list
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Explain the scan on stderr: which directories are scanned and why files are
+    /// skipped. Repeat (-vv) for more detail
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Plain ASCII output without color, for terminals that garble anything else
+    #[arg(long, global = true)]
+    ascii: bool,
+
+    /// Keep running and rescan for new files until interrupted with Ctrl-C
+    #[arg(long, conflicts_with_all = ["json", "list", "multi", "pick", "newest", "clip"])]
+    watch: bool,
+
+    /// Seconds between rescans in watch mode
+    #[arg(long, value_name = "SECONDS", default_value_t = 2, requires = "watch")]
+    interval: u64,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Move the most recently moved file back to where it came from
+    Undo,
+    /// Create a configuration file by answering a few questions
+    Init,
+    /// Count the recent files and their size by extension and source directory
+    Stats,
+    /// Print the configuration in effect, after the environment and command-line flags, as JSON
+    Config,
+    /// Print a shell completion script to stdout
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
 }
 
 fn main() {
+    // Invalid arguments count as configuration errors, help and version output as success
+    let cli = Cli::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        process::exit(if e.use_stderr() { EXIT_CONFIG_ERROR } else { 0 });
+    });
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+    // Only this crate logs, the messages of its dependencies stay off
+    let log_level = match cli.verbose {
+        0 => log::LevelFilter::Off,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_module("m", log_level)
+        .format_timestamp(None)
+        .format_target(false)
+        .init();
+    // Commands that run before the config is read only go by the flags and M_ASCII
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+    if ascii_from_env(&cli) {
+        ASCII.store(true, Ordering::Relaxed);
+        ColorMode::Never.apply();
+        inquire::set_global_render_config(RenderConfig::empty());
+    }
+
+    match cli.command {
+        Some(Command::Undo) => {
+            if let Err(e) = undo_last_move(cli.quiet) {
+                eprintln!("Failed to undo: {}", e);
+                process::exit(EXIT_MOVE_FAILED);
+            }
+            return;
+        }
+        Some(Command::Init) => {
+            if let Err(e) = init_config(cli.config.as_deref(), cli.format) {
+                eprintln!("Failed to create configuration: {}", e);
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+            return;
+        }
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(shell, &mut Cli::command(), "m", &mut std::io::stdout());
+            return;
+        }
+        Some(Command::Stats) | Some(Command::Config) | None => {}
+    }
+
     // Read configuration
-    let config = match read_config() {
+    let mut config = match read_config(cli.config.as_deref(), cli.format, cli.quiet) {
         Ok(config) => config,
         Err(e) => {
             eprintln!("Failed to read configuration: {}", e);
-            process::exit(1);
+            process::exit(EXIT_CONFIG_ERROR);
+        }
+    };
+
+    apply_cli_overrides(&mut config, &cli);
+    QUIET.store(config.quiet, Ordering::Relaxed);
+    // The config file was checked when it was read, a list file given here was not
+    if cli.source_file.is_some()
+        && let Err(problems) = validate_config(&config)
+    {
+        eprintln!(
+            "Invalid settings with --source-file:\n  - {}",
+            problems.join("\n  - ")
+        );
+        process::exit(EXIT_CONFIG_ERROR);
+    }
+    if let Some(Command::Config) = cli.command {
+        match serde_json::to_string_pretty(&config) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Failed to serialize configuration: {}", e);
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+        }
+        return;
+    }
+    if config.ascii {
+        ASCII.store(true, Ordering::Relaxed);
+        ColorMode::Never.apply();
+        inquire::set_global_render_config(RenderConfig::empty());
+    } else {
+        config.color.apply();
+    }
+    let mut options = MoveOptions::new(expand_path(&config.target_dir));
+    options.mode = if cli.copy {
+        TransferMode::Copy
+    } else if cli.link {
+        TransferMode::HardLink
+    } else if cli.symlink {
+        TransferMode::Symlink
+    } else if cli.delete {
+        TransferMode::Delete
+    } else {
+        TransferMode::Move
+    };
+    options.use_trash = cli.trash;
+    options.dry_run = cli.dry_run;
+    options.on_conflict = if let Some(on_conflict) = cli.on_conflict {
+        on_conflict
+    } else if cli.force {
+        OnConflict::Overwrite
+    } else if cli.no_clobber {
+        OnConflict::Skip
+    } else {
+        OnConflict::Prompt
+    };
+    options.open_after = cli.open;
+    options.remember_destination = config.remember_destination;
+    options.verify = cli.verify;
+    options.type_routing = config.type_routing.clone();
+    options.date_subdir = config.date_subdir.clone();
+    options.retry = RetryPolicy {
+        retries: config.retry_count,
+        delay: Duration::from_millis(config.retry_delay_ms),
+    };
+    options.post_move_hook = config.post_move_hook.clone();
+    options.extract = cli.extract;
+    options.delete_archive = cli.delete_archive;
+    options.leave_link = cli.leave_link;
+    options.fanout = config.fanout.iter().map(|dir| expand_path(dir)).collect();
+    options.quiet = config.quiet;
+    options.ascii = config.ascii;
+    // An explicit --to always wins over the destination picker and the remembered destination
+    let explicit_target = !cli.to.is_empty();
+    let budget = match parse_size_bound(&cli.budget, "--budget", config.size_units) {
+        Ok(budget) => budget,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_CONFIG_ERROR);
         }
     };
 
+    if let Some(Command::Stats) = cli.command {
+        // The summary covers the whole window, not just the files that would be listed
+        config.limit = 0;
+        print_activity_stats(&config);
+        return;
+    }
+
+    if cli.watch {
+        watch(&config, &options, explicit_target, &cli);
+    }
+
     // Find recently created files
-    let files = match find_recent_files(&config) {
-        Ok(files) => files,
+    let (files, stats) = match find_recent_files(&config) {
+        Ok(result) => result,
         Err(e) => {
             eprintln!("Failed to find files: {}", e);
-            process::exit(1);
+            process::exit(EXIT_SCAN_ERROR);
         }
     };
 
+    if cli.json {
+        match serde_json::to_string_pretty(&files) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Failed to serialize files: {}", e);
+                process::exit(EXIT_SCAN_ERROR);
+            }
+        }
+        if files.is_empty() {
+            process::exit(EXIT_NO_FILES);
+        }
+        return;
+    }
+
+    if cli.list {
+        for file in &files {
+            println!("{}", file);
+        }
+        if files.is_empty() {
+            process::exit(EXIT_NO_FILES);
+        }
+        return;
+    }
+
     if files.is_empty() {
-        println!(
+        if stats.dirs_walked == 0
+            && let Some(hint) = missing_sources_hint(&config)
+        {
+            report!("{}", styled(hint.red()));
+            process::exit(EXIT_NO_FILES);
+        }
+        let window = if config.time_limit.is_unlimited() {
+            String::new()
+        } else {
+            format!(" in the last {}", config.time_limit)
+        };
+        report!(
             "{}",
-            format!(
-                "No new files found in the last {} minutes",
-                config.time_limit
+            styled(
+                format!(
+                    "No new files found{} across {} dir{}",
+                    window,
+                    stats.dirs_walked,
+                    if stats.dirs_walked == 1 { "" } else { "s" }
+                )
+                .red()
             )
-            .red()
+        );
+        process::exit(EXIT_NO_FILES);
+    }
+
+    report!("{}", styled(stats.to_string().cyan()));
+
+    if cli.multi {
+        let confirm = !cli.force && !cli.dry_run;
+        move_multiple(
+            files,
+            &options,
+            &config,
+            explicit_target,
+            budget,
+            confirm,
+            cli.atomic,
         );
         return;
     }
 
     // Present files for selection
-    let selected_file = match select_file(files) {
+    let selected = if let Some(n) = cli.pick {
+        let count = files.len();
+        match files.into_iter().nth(n.wrapping_sub(1)) {
+            Some(file) => Ok(file),
+            None => {
+                eprintln!(
+                    "Cannot pick file {}, there {} {} file{}",
+                    n,
+                    if count == 1 { "is" } else { "are" },
+                    count,
+                    if count == 1 { "" } else { "s" }
+                );
+                process::exit(EXIT_CONFIG_ERROR);
+            }
+        }
+    } else if cli.newest {
+        // The list may be sorted by something other than age, so look for the newest file,
+        // preferring the one listed first on a tie
+        Ok(files
+            .into_iter()
+            .rev()
+            .max_by_key(|file| file.created_timestamp)
+            .expect("the file list is not empty"))
+    } else {
+        refine_files(files, config.refine_threshold).and_then(|files| {
+            let entries = list_entries(files, &config);
+            if cli.preview {
+                select_file_with_preview(entries, config.page_size, options.mode.verb())
+            } else {
+                select_file(entries, config.page_size, options.mode.verb())
+            }
+        })
+    };
+    let selected_file = match selected {
         Ok(file) => file,
         Err(_) => {
-            println!("No file selected");
-            return;
+            report!("No file selected");
+            process::exit(EXIT_CANCELED);
         }
     };
 
+    if cli.clip {
+        if let Err(e) = copy_path_to_clipboard(&selected_file.path) {
+            eprintln!("Failed to copy to clipboard: {}", e);
+            process::exit(EXIT_MOVE_FAILED);
+        }
+        return;
+    }
+
+    let ask_before_deleting = options.mode == TransferMode::Delete && !cli.force && !cli.dry_run;
+    if ask_before_deleting && !confirm_delete(&selected_file, options.use_trash) {
+        report!("Operation canceled");
+        process::exit(EXIT_CANCELED);
+    }
+
+    if let Some(remote) = &cli.remote {
+        let started = Instant::now();
+        match upload_file(&selected_file, remote, &options) {
+            Ok(MoveOutcome::Moved) => print_transfer_footer(
+                &options,
+                config.size_units,
+                1,
+                selected_file.size,
+                started.elapsed(),
+            ),
+            Ok(MoveOutcome::Skipped) => {}
+            Err(e) => {
+                eprintln!("Failed to upload file: {}", e);
+                process::exit(EXIT_MOVE_FAILED);
+            }
+        }
+        return;
+    }
+
+    let allow_picker = cli.pick.is_none() && !cli.newest;
+    match resolve_destination(
+        &selected_file,
+        &options,
+        &config,
+        explicit_target,
+        allow_picker,
+    ) {
+        Ok(Some(destination)) => options.target_dir = destination,
+        Ok(None) => {}
+        Err(_) => {
+            report!("No destination selected");
+            process::exit(EXIT_CANCELED);
+        }
+    }
+
     // Move the selected file
-    if let Err(e) = move_file(&selected_file) {
-        eprintln!("Failed to move file: {}", e);
-        process::exit(1);
+    let started = Instant::now();
+    match move_file(&selected_file, &options) {
+        Ok(MoveOutcome::Moved) => {
+            print_transfer_footer(
+                &options,
+                config.size_units,
+                1,
+                selected_file.size,
+                started.elapsed(),
+            );
+            open_target_dir(&options);
+        }
+        Ok(MoveOutcome::Skipped) => {}
+        Err(e) => {
+            eprintln!("Failed to move file: {}", e);
+            process::exit(EXIT_MOVE_FAILED);
+        }
     }
 }
 
-fn read_config() -> Result<Config, Box<dyn std::error::Error>> {
-    let config_path = dirs::home_dir()
-        .ok_or("Could not find home directory")?
-        .join(".config")
-        .join("m")
-        .join("m.json");
+/// Ask before deleting a file, defaulting to no
+fn confirm_delete(file: &FileInfo, use_trash: bool) -> bool {
+    let question = if use_trash {
+        format!("Move '{}' to the trash?", file.name)
+    } else {
+        format!("Permanently delete '{}'?", file.name)
+    };
+    matches!(
+        Confirm::new(&question)
+            .with_default(false)
+            .with_help_message("Use --force to delete without asking")
+            .prompt(),
+        Ok(true)
+    )
+}
 
-    if !config_path.exists() {
-        // Create default config if it doesn't exist
-        let config_dir = config_path.parent().unwrap();
-        fs::create_dir_all(config_dir)?;
+/// Where a single selected file goes when it is not the target directory: the
+/// picked destination, or the remembered one. Fails when the picker is canceled.
+fn resolve_destination(
+    file: &FileInfo,
+    options: &MoveOptions,
+    config: &Config,
+    explicit_target: bool,
+    allow_picker: bool,
+) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    if explicit_target || options.mode == TransferMode::Delete {
+        return Ok(None);
+    }
 
-        let home_dir = dirs::home_dir().unwrap();
-        let default_config = Config {
-            source_dir: vec![home_dir.join("Downloads").to_string_lossy().to_string()],
-            time_limit: 20,
-            black_list: vec![],
+    if !config.destinations.is_empty() && allow_picker {
+        let last = config
+            .remember_destination
+            .then(|| last_destination(file))
+            .flatten();
+        pick_destination(config, &options.target_dir, last).map(Some)
+    } else if config.remember_destination {
+        Ok(remembered_destination(file))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Scan, select and move in a loop, waiting for files to show up when there are none.
+/// Escape in the selector rescans, Ctrl-C exits.
+fn watch(config: &Config, options: &MoveOptions, explicit_target: bool, cli: &Cli) -> ! {
+    let interval = Duration::from_secs(cli.interval.max(1));
+    let (rescan_requests, requested) = mpsc::channel();
+    let (found, scans) = mpsc::channel();
+    let scanner_config = config.clone();
+    thread::spawn(move || scan_in_background(scanner_config, interval, requested, found));
+
+    let mut waiting = false;
+    let mut generation = 0;
+    let mut rescan = false;
+    loop {
+        // After a selection the list is out of date, so wait for a scan made since
+        if std::mem::take(&mut rescan) {
+            generation += 1;
+            let _ = rescan_requests.send(generation);
+        }
+        let (files, stats) = match next_scan(&scans, generation) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Failed to find files: {}", e);
+                process::exit(EXIT_SCAN_ERROR);
+            }
         };
 
-        let json_content = serde_json::to_string_pretty(&default_config)?;
+        if files.is_empty() {
+            if !waiting {
+                if stats.dirs_walked == 0
+                    && let Some(hint) = missing_sources_hint(config)
+                {
+                    warning!("{}", styled(hint.yellow()));
+                }
+                report!(
+                    "{}",
+                    styled(
+                        format!(
+                            "Waiting for new files from the last {}, press Ctrl-C to stop",
+                            config.time_limit
+                        )
+                        .cyan()
+                    )
+                );
+                waiting = true;
+            }
+            continue;
+        }
+        waiting = false;
+        rescan = true;
 
-        fs::write(&config_path, json_content)?;
-        println!(
-            "Created default configuration file at: {}",
-            config_path.display()
-        );
-        return Ok(default_config);
-    }
+        let verb = options.mode.verb();
+        let selected = select_file_live(files, stats, config, verb, &scans).and_then(|file| {
+            if !cli.preview || confirm_preview(&file, verb)? {
+                Ok(file)
+            } else {
+                Err(InquireError::OperationCanceled.into())
+            }
+        });
+        let selected_file = match selected {
+            Ok(file) => file,
+            Err(e) => {
+                rescan_or_exit(&*e);
+                continue;
+            }
+        };
 
-    let json_content = fs::read_to_string(&config_path)?;
-    let config: Config = serde_json::from_str(&json_content)?;
+        let ask_before_deleting = options.mode == TransferMode::Delete && !cli.force;
+        if ask_before_deleting && !confirm_delete(&selected_file, options.use_trash) {
+            continue;
+        }
+
+        let mut options = options.clone();
+        match resolve_destination(&selected_file, &options, config, explicit_target, true) {
+            Ok(Some(destination)) => options.target_dir = destination,
+            Ok(None) => {}
+            Err(e) => {
+                rescan_or_exit(&*e);
+                continue;
+            }
+        }
 
-    Ok(config)
+        // A failed move is reported, but does not end the watch
+        let started = Instant::now();
+        match move_file(&selected_file, &options) {
+            Ok(MoveOutcome::Moved) => {
+                print_transfer_footer(
+                    &options,
+                    config.size_units,
+                    1,
+                    selected_file.size,
+                    started.elapsed(),
+                );
+                open_target_dir(&options);
+            }
+            Ok(MoveOutcome::Skipped) => {}
+            Err(e) => eprintln!("Failed to move file: {}", e),
+        }
+    }
 }
 
-fn find_recent_files(config: &Config) -> Result<Vec<FileInfo>, Box<dyn std::error::Error>> {
-    let mut files = Vec::new();
-    let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-    let time_limit_seconds = config.time_limit * 60;
-    let not_before = current_time - time_limit_seconds;
+/// One scan of the watch's scanner thread
+struct WatchScan {
+    // The latest rescan request made before the scan started
+    generation: u64,
+    result: Result<(Vec<FileInfo>, ScanStats), String>,
+    // New files that could not be shown as a desktop notification
+    announcements: Vec<String>,
+}
 
-    // Iterate over source directories
-    for source_dir in &config.source_dir {
-        let source_path = Path::new(source_dir);
-        if !source_path.exists() {
-            continue;
+/// Rescan every `interval`, or sooner when asked to, and notify about new files as
+/// they show up rather than only when the list is next shown. Scan warnings are
+/// printed for the first scan only, so they do not pile up over the list.
+fn scan_in_background(
+    mut config: Config,
+    interval: Duration,
+    requested: mpsc::Receiver<u64>,
+    found: mpsc::Sender<WatchScan>,
+) {
+    let mut generation = 0;
+    let mut seen = None;
+    loop {
+        let result = find_recent_files(&config).map_err(|e| e.to_string());
+        let announcements = match &result {
+            Ok((files, _)) if config.notify => {
+                announce_new_files(files, &mut seen, config.size_units)
+            }
+            _ => Vec::new(),
+        };
+        let scan = WatchScan {
+            generation,
+            result,
+            announcements,
+        };
+        if found.send(scan).is_err() {
+            return;
+        }
+        config.quiet = true;
+
+        match requested.recv_timeout(interval) {
+            Ok(request) => generation = request,
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
         }
-        // Recursively scan directories
-        scan_directory(source_path, config, &mut files, not_before)?;
+        generation = requested.try_iter().last().unwrap_or(generation);
     }
+}
 
-    // Sort by creation time (newest first)
-    files.sort_by(|a, b| b.created_timestamp.cmp(&a.created_timestamp));
+/// Wait for a scan made after rescan request `generation`, skipping older ones
+fn next_scan(
+    scans: &mpsc::Receiver<WatchScan>,
+    generation: u64,
+) -> Result<(Vec<FileInfo>, ScanStats), String> {
+    loop {
+        let scan = scans
+            .recv()
+            .map_err(|_| "the scanner thread stopped".to_string())?;
+        for announcement in &scan.announcements {
+            report!("{}", styled(announcement.as_str().cyan()));
+        }
+        if scan.generation >= generation {
+            return scan.result;
+        }
+    }
+}
 
-    // Calculate column widths for the entire list
-    let time_width = files
-        .iter()
-        .map(|f| f.created_time.len())
-        .max()
-        .unwrap_or(8);
+/// Notify about the files that showed up since the last scan. The files already
+/// there when the watch started are not new. Returns the announcements for the
+/// terminal where no desktop notification could be shown.
+fn announce_new_files(
+    files: &[FileInfo],
+    seen: &mut Option<HashSet<PathBuf>>,
+    units: SizeUnits,
+) -> Vec<String> {
+    let mut unshown = Vec::new();
+    if let Some(seen) = seen {
+        for file in files.iter().filter(|file| !seen.contains(&file.path)) {
+            let message = format!("{} ({})", file.name, format_size(file.size, units));
+            if let Err(e) = desktop_notification("New file", &message) {
+                // Such as over ssh, without a desktop to show it on
+                log::debug!("Could not show a desktop notification: {}", e);
+                unshown.push(format!("New file: {}", message));
+            }
+        }
+    }
+    *seen = Some(files.iter().map(|file| file.path.clone()).collect());
+    unshown
+}
+
+/// Show a notification through the desktop's notification service: D-Bus on Linux
+/// and BSD, Notification Center on macOS and toasts on Windows
+fn desktop_notification(title: &str, body: &str) -> Result<(), Box<dyn std::error::Error>> {
+    notify_rust::Notification::new()
+        .appname("m")
+        .summary(title)
+        .body(body)
+        .show()?;
+    Ok(())
+}
 
-    let size_width = files
+/// Explain an empty scan that found no source directory at all, instead of
+/// reporting no new files as if the directories had been looked at
+fn missing_sources_hint(config: &Config) -> Option<String> {
+    let missing = missing_source_dirs(config);
+    if missing.is_empty() {
+        return None;
+    }
+    let list = missing
         .iter()
-        .map(|f| format_size(f.size).len())
-        .max()
-        .unwrap_or(10);
+        .map(|dir| format!("'{}'", dir.display()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(format!(
+        "None of the source directories exist ({}), check source_dir in the configuration",
+        list
+    ))
+}
 
-    // Update all files with calculated column widths
-    for file in &mut files {
-        file.time_width = time_width + 2;
-        file.size_width = size_width + 2;
+/// Escape goes back to scanning, Ctrl-C ends the watch, anything else (such as
+/// no terminal to prompt on) is fatal
+fn rescan_or_exit(error: &(dyn std::error::Error + 'static)) {
+    match error.downcast_ref::<InquireError>() {
+        Some(InquireError::OperationCanceled) => {}
+        Some(InquireError::OperationInterrupted) => process::exit(0),
+        _ => {
+            eprintln!("Failed to prompt: {}", error);
+            process::exit(EXIT_CANCELED);
+        }
     }
+}
 
-    Ok(files)
+/// Command-line flags take precedence over the config file, including per-directory settings
+/// Whether `--ascii` was given or `M_ASCII` is set to anything but `0`
+fn ascii_from_env(cli: &Cli) -> bool {
+    cli.ascii || std::env::var("M_ASCII").is_ok_and(|value| !value.is_empty() && value != "0")
 }
 
-fn scan_directory(
-    dir_path: &Path,
+fn apply_cli_overrides(config: &mut Config, cli: &Cli) {
+    config.quiet |= cli.quiet;
+    config.ascii |= ascii_from_env(cli);
+    if let Some((to, more)) = cli.to.split_first() {
+        config.target_dir = to.clone();
+        config.fanout = more.to_vec();
+    }
+    if !cli.ext.is_empty() {
+        config.extensions = cli.ext.clone();
+        for source in &mut config.source_dir {
+            source.clear_extensions();
+        }
+    }
+    if cli.source_file.is_some() {
+        config.source_list_file = cli.source_file.clone();
+    }
+    if cli.name.is_some() {
+        config.name_contains = cli.name.clone();
+    }
+    if cli.only_empty {
+        config.only_empty = true;
+        config.skip_empty = false;
+    }
+    if cli.min_size.is_some() {
+        config.min_size = cli.min_size.clone();
+    }
+    if cli.max_size.is_some() {
+        config.max_size = cli.max_size.clone();
+    }
+    if cli.depth.is_some() {
+        config.max_depth = cli.depth;
+    }
+    // Not recursing is the most restrictive depth, whatever else is configured
+    if cli.no_recursive {
+        config.max_depth = Some(0);
+    }
+    let since = if cli.all {
+        Some(TimeLimit::UNLIMITED)
+    } else {
+        cli.since.or(cli.since_file)
+    };
+    if let Some(since) = since {
+        config.time_limit = since;
+        for source in &mut config.source_dir {
+            source.clear_time_limit();
+        }
+    }
+    if let Some(sort) = cli.sort {
+        config.sort_by = sort;
+    }
+    if cli.cleanup {
+        config.sort_by = SortBy::Time;
+        config.reverse = true;
+    }
+    if cli.reverse {
+        config.reverse = !config.reverse;
+    }
+    if let Some(limit) = cli.limit {
+        config.limit = limit;
+    }
+    if cli.warn_age.is_some() {
+        config.warn_age = cli.warn_age;
+    }
+}
+
+fn move_multiple(
+    files: Vec<FileInfo>,
+    options: &MoveOptions,
     config: &Config,
-    files: &mut Vec<FileInfo>,
-    not_before: u64,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let entries = fs::read_dir(dir_path)?;
+    explicit_target: bool,
+    budget: Option<u64>,
+    confirm: bool,
+    atomic: bool,
+) {
+    let selected_files = match refine_files(files, config.refine_threshold).and_then(|files| {
+        select_files(
+            list_entries(files, config),
+            config.page_size,
+            options.mode.verb(),
+        )
+    }) {
+        Ok(files) if !files.is_empty() => files,
+        _ => {
+            report!("No file selected");
+            process::exit(EXIT_CANCELED);
+        }
+    };
 
-    for entry in entries {
-        let entry = entry?;
-        let metadata = entry.metadata()?;
-        let file_name_str = entry.file_name().to_string_lossy().to_string();
+    let total_size: u64 = selected_files.iter().map(|f| f.size).sum();
+    if let Some(budget) = budget.filter(|&budget| total_size > budget) {
+        let proceed = Confirm::new(&format!(
+            "The selected files take {}, {} over the budget of {}. Move them anyway?",
+            format_size(total_size, config.size_units),
+            format_size(total_size - budget, config.size_units),
+            format_size(budget, config.size_units)
+        ))
+        .with_default(false)
+        .prompt();
+        if !matches!(proceed, Ok(true)) {
+            report!("Operation canceled");
+            process::exit(EXIT_CANCELED);
+        }
+    }
 
-        // Skip if the path or file name contains any blacklisted string
-        if config.black_list.iter().any(|blacklisted| {file_name_str.contains(blacklisted)}) {
-            continue;
+    // The whole batch goes to one picked destination
+    let deleting = options.mode == TransferMode::Delete;
+    let picked = if !explicit_target && !deleting && !config.destinations.is_empty() {
+        let last = config
+            .remember_destination
+            .then(|| last_destination(&selected_files[0]))
+            .flatten();
+        match pick_destination(config, &options.target_dir, last) {
+            Ok(destination) => Some(destination),
+            Err(_) => {
+                report!("No destination selected");
+                process::exit(EXIT_CANCELED);
+            }
         }
+    } else {
+        None
+    };
+    let use_remembered = !explicit_target && !deleting && config.remember_destination;
+    let batch: Vec<(&FileInfo, Option<MoveOptions>)> = selected_files
+        .iter()
+        .map(|file_info| {
+            let remembered = picked
+                .clone()
+                .or_else(|| {
+                    use_remembered
+                        .then(|| remembered_destination(file_info))
+                        .flatten()
+                })
+                .map(|target_dir| {
+                    let mut options = options.clone();
+                    options.target_dir = target_dir;
+                    options
+                });
+            (file_info, remembered)
+        })
+        .collect();
 
-        // Skip hidden files and directories
-        if file_name_str.starts_with('.') {
-            continue;
+    if confirm {
+        report!("");
+        for (file_info, remembered) in &batch {
+            if deleting {
+                report!("  {}", file_info.display_name);
+                continue;
+            }
+            let file_options = remembered.as_ref().unwrap_or(options);
+            let targets: Vec<String> = std::iter::once(&file_options.target_dir)
+                .chain(&file_options.fanout)
+                .map(|dir| describe_dir(dir))
+                .collect();
+            report!("  {} -> {}", file_info.display_name, targets.join(", "));
+        }
+        let proceed = Confirm::new(&format!(
+            "{} {} file{} ({})?",
+            capitalize(options.mode.verb()),
+            batch.len(),
+            if batch.len() == 1 { "" } else { "s" },
+            format_size(total_size, config.size_units)
+        ))
+        .with_default(!deleting)
+        .with_help_message("Use --force to skip this question")
+        .prompt();
+        if !matches!(proceed, Ok(true)) {
+            report!("Operation canceled");
+            process::exit(EXIT_CANCELED);
         }
+    }
 
-        if metadata.is_file() {
-            // Check if file was created within the time limit
-            let created_time = metadata.created()?.duration_since(UNIX_EPOCH)?.as_secs();
-
-            if created_time >= not_before {
-                let file_path = entry.path();
-                let file_name = file_name_str;
-                let size = metadata.len();
-
-                // Format creation time as HH:MM in local timezone
-                let created_datetime = chrono::DateTime::from_timestamp(created_time as i64, 0)
-                    .unwrap_or_default()
-                    .with_timezone(&chrono::Local);
-                let time_str = created_datetime.format("%H:%M").to_string();
-
-                files.push(FileInfo {
-                    path: file_path,
-                    name: file_name,
-                    size,
-                    created_time: time_str,
-                    created_timestamp: created_time,
-                    time_width: 5, // Will be updated later
-                    size_width: 8, // Will be updated later
-                });
+    let started = Instant::now();
+    let mut moved = 0;
+    let mut skipped = 0;
+    let mut failed = 0;
+    let mut moved_bytes = 0;
+
+    // A dry run shows the same plan either way
+    if atomic && !options.dry_run {
+        let batch: Vec<(&FileInfo, &MoveOptions)> = batch
+            .iter()
+            .map(|(file_info, remembered)| (*file_info, remembered.as_ref().unwrap_or(options)))
+            .collect();
+        // Taken names are numbered instead of skipped, and a failure leaves every file in place
+        match move_atomically(&batch) {
+            Ok(()) => {
+                moved = batch.len();
+                moved_bytes = total_size;
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = batch.len();
+            }
+        }
+    } else {
+        // Confirmation for existing files is asked per file inside move_file
+        for (file_info, remembered) in batch {
+            match move_file(file_info, remembered.as_ref().unwrap_or(options)) {
+                Ok(MoveOutcome::Moved) => {
+                    moved += 1;
+                    moved_bytes += file_info.size;
+                }
+                Ok(MoveOutcome::Skipped) => skipped += 1,
+                Err(e) => {
+                    eprintln!("Failed to move '{}': {}", file_info.name, e);
+                    failed += 1;
+                }
             }
-        } else if metadata.is_dir() {
-            // Recursively scan subdirectories
-            scan_directory(&entry.path(), config, files, not_before)?;
         }
     }
 
-    Ok(())
+    if moved > 0 {
+        open_target_dir(options);
+    }
+
+    let verb = if options.dry_run {
+        format!("would be {}", options.mode.past_tense())
+    } else {
+        options.mode.past_tense().to_string()
+    };
+    let summary = format!("{} {}, {} skipped, {} failed", moved, verb, skipped, failed);
+    print_transfer_footer(
+        options,
+        config.size_units,
+        moved,
+        moved_bytes,
+        started.elapsed(),
+    );
+    if failed > 0 {
+        report!("{}", styled(summary.red()));
+        process::exit(EXIT_MOVE_FAILED);
+    } else if skipped > 0 {
+        report!("{}", styled(summary.yellow()));
+    } else {
+        report!("{}", styled(summary.green()));
+    }
 }
 
-fn select_file(files: Vec<FileInfo>) -> Result<FileInfo, Box<dyn std::error::Error>> {
-    let selected = Select::new("Select a file to move:", files)
-        .with_help_message("Use arrow keys to navigate, press Enter to select")
-        .with_formatter(&|x| x.value.name.clone())
-        .prompt()?;
+/// Print the number and size of the recent files, grouped by extension and by
+/// source directory, largest first
+fn print_activity_stats(config: &Config) {
+    let (files, stats) = match find_recent_files(config) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Failed to find files: {}", e);
+            process::exit(EXIT_SCAN_ERROR);
+        }
+    };
+    if files.is_empty() {
+        report!(
+            "{}",
+            styled(format!("No new files found in the last {}", config.time_limit).red())
+        );
+        process::exit(EXIT_NO_FILES);
+    }
+
+    let mut by_extension: HashMap<String, (usize, u64)> = HashMap::new();
+    let mut by_source: HashMap<String, (usize, u64)> = HashMap::new();
+    for file in &files {
+        let extension = if file.is_dir {
+            "(directory)".to_string()
+        } else {
+            file.path
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase())
+                .unwrap_or_else(|| "(none)".to_string())
+        };
+        for (groups, key) in [
+            (&mut by_extension, extension),
+            (&mut by_source, file.source_root.display().to_string()),
+        ] {
+            let (count, size) = groups.entry(key).or_default();
+            *count += 1;
+            *size += file.size;
+        }
+    }
+
+    println!("{}", styled(stats.to_string().cyan()));
+    for (title, groups) in [("Extension", by_extension), ("Source directory", by_source)] {
+        let mut rows = groups.into_iter().collect::<Vec<_>>();
+        rows.sort_by(|(a_key, (_, a_size)), (b_key, (_, b_size))| {
+            b_size.cmp(a_size).then_with(|| a_key.cmp(b_key))
+        });
+
+        let key_width = rows
+            .iter()
+            .map(|(key, _)| key.width())
+            .chain([title.width()])
+            .max()
+            .unwrap_or(0);
+        let count_width = rows
+            .iter()
+            .map(|(_, (count, _))| count.to_string().len())
+            .chain(["Files".len()])
+            .max()
+            .unwrap_or(0);
+
+        println!();
+        println!(
+            "{}",
+            styled(
+                format!(
+                    "{}  {:>count_width$}  Size",
+                    pad_to_width(title, key_width),
+                    "Files"
+                )
+                .bold()
+            )
+        );
+        for (key, (count, size)) in rows {
+            println!(
+                "{}  {:>count_width$}  {}",
+                pad_to_width(&key, key_width),
+                count,
+                format_size(size, config.size_units)
+            );
+        }
+    }
+}
+
+fn print_transfer_footer(
+    options: &MoveOptions,
+    units: SizeUnits,
+    count: usize,
+    bytes: u64,
+    elapsed: Duration,
+) {
+    if options.dry_run || count == 0 {
+        return;
+    }
+    report!(
+        "{} {} file{} ({}) in {:.2}s",
+        capitalize(options.mode.past_tense()),
+        count,
+        if count == 1 { "" } else { "s" },
+        format_size(bytes, units),
+        elapsed.as_secs_f64()
+    );
+}
 
-    Ok(selected)
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
 }
 
-fn move_file(file_info: &FileInfo) -> Result<(), Box<dyn std::error::Error>> {
-    let target_path = Path::new(&file_info.name);
+/// Ask for the basic settings and write them as a new config file
+fn init_config(
+    custom_path: Option<&Path>,
+    format: ConfigFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // A given path is written in the format its extension names
+    let (config_path, format) = match custom_path {
+        Some(path) => (path.to_path_buf(), ConfigFormat::of_path(path)),
+        None => (
+            config_dir()?.join(format!("m.{}", format.extension())),
+            format,
+        ),
+    };
 
-    // Check if file already exists in current directory
-    if target_path.exists() {
-        let overwrite = Confirm::new(&format!(
-            "File '{}' already exists. Overwrite?",
-            file_info.name
+    if config_path.exists()
+        && !Confirm::new(&format!(
+            "'{}' already exists. Overwrite it?",
+            config_path.display()
         ))
         .with_default(false)
-        .with_help_message("This will permanently replace the existing file")
+        .prompt()?
+    {
+        report!("Operation canceled");
+        return Ok(());
+    }
+
+    let mut config = default_config();
+    let default_sources = config
+        .source_dir
+        .iter()
+        .map(|source| source.path().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let sources = Text::new("Directories to watch for new files:")
+        .with_default(&default_sources)
+        .with_help_message("Comma separated, '~' and $VARIABLES are expanded")
         .prompt()?;
+    config.source_dir = sources
+        .split(',')
+        .map(str::trim)
+        .filter(|source| !source.is_empty())
+        .map(|source| SourceDir::Path(source.to_string()))
+        .collect();
+
+    let time_limit = Text::new("How recent files must be:")
+        .with_default(&format!("{}m", config.time_limit.seconds() / 60))
+        .with_help_message("A number with a unit, e.g. 30m, 2h or 1d")
+        .with_validator(|value: &str| {
+            Ok(match parse_time_limit(value) {
+                Ok(_) => Validation::Valid,
+                Err(e) => Validation::Invalid(e.into()),
+            })
+        })
+        .prompt()?;
+    config.time_limit = parse_time_limit(&time_limit)?;
+
+    config.target_dir = Text::new("Where to move files to:")
+        .with_default(&config.target_dir)
+        .with_help_message("'.' is the directory m is run from")
+        .prompt()?;
+
+    validate_config(&config).map_err(|problems| {
+        format!(
+            "The configuration is invalid:\n  - {}",
+            problems.join("\n  - ")
+        )
+    })?;
+
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&config_path, format.render(&config)?)?;
+    report!(
+        "{}",
+        styled(format!("Wrote configuration to '{}'", config_path.display()).green())
+    );
+    Ok(())
+}
 
-        if !overwrite {
-            println!("Operation canceled");
-            return Ok(());
+fn select_file(
+    files: Vec<ListEntry>,
+    page_size: Option<usize>,
+    verb: &str,
+) -> Result<FileInfo, Box<dyn std::error::Error>> {
+    let message = format!("Select a file to {}:", verb);
+    let mut cursor = 0;
+    loop {
+        let selected = Select::new(&message, files.clone())
+            .with_help_message("Use arrow keys to navigate, type to search, press Enter to select")
+            .with_formatter(&|x| x.value.label())
+            .with_scorer(&score_file_name)
+            .with_starting_cursor(cursor)
+            .with_page_size(list_page_size(page_size))
+            .raw_prompt()?;
+
+        match selected.value {
+            ListEntry::File(file) => return Ok(file),
+            // Headers only label the groups, go back to the list at the first file below it
+            ListEntry::Header { .. } => cursor = selected.index + 1,
         }
     }
+}
 
-    // Copy the file
-    if let Err(copy_err) = fs::copy(&file_info.path, target_path) {
-        return Err(copy_err.into());
+/// Rows of the file list: the configured `page_size`, or as many as fit the terminal
+/// below the prompt and above the help line
+fn list_page_size(page_size: Option<usize>) -> usize {
+    const INQUIRE_DEFAULT: usize = 7;
+    if let Some(page_size) = page_size.filter(|&rows| rows > 0) {
+        return page_size;
     }
-    
-    // Remove the original file
-    if let Err(remove_err) = fs::remove_file(&file_info.path) {
-        println!(
-            "{}",
-            format!(
-                "File '{}' was copied, but failed to delete the original: {}",
-                file_info.name, remove_err
-            )
-            .yellow()
+    match terminal::size() {
+        // The stats line, the prompt, the help line and one to spare
+        Ok((_, rows)) if rows > 0 => usize::from(rows).saturating_sub(4).max(3),
+        _ => INQUIRE_DEFAULT,
+    }
+}
+
+/// Fuzzy match the typed filter against the shown name only, so "rpt" ranks
+/// "quarterly-report.pdf" without the time and size columns getting in the way
+fn score_file_name(input: &str, entry: &ListEntry, _display: &str, _index: usize) -> Option<i64> {
+    match entry {
+        // Group headers are hidden while searching
+        ListEntry::Header { .. } if !input.is_empty() => None,
+        ListEntry::Header { .. } => Some(0),
+        ListEntry::File(file) => score_name(&file.display_name, input),
+    }
+}
+
+fn score_name(name: &str, input: &str) -> Option<i64> {
+    static MATCHER: OnceLock<SkimMatcherV2> = OnceLock::new();
+    MATCHER
+        .get_or_init(|| SkimMatcherV2::default().ignore_case())
+        .fuzzy_match(name, input)
+}
+
+/// Narrow the files down by name before listing them while more than
+/// `threshold` match. An empty filter lists all that are left.
+fn refine_files(
+    mut files: Vec<FileInfo>,
+    threshold: Option<usize>,
+) -> Result<Vec<FileInfo>, Box<dyn std::error::Error>> {
+    let Some(threshold) = threshold else {
+        return Ok(files);
+    };
+
+    while files.len() > threshold {
+        let names = NameSuggester(files.iter().map(|file| file.display_name.clone()).collect());
+        let help = format!(
+            "{} files match, type part of a name to narrow them down or press Enter to list them all",
+            files.len()
         );
-        return Ok(())
+        let filter = Text::new("Filter files:")
+            .with_help_message(&help)
+            .with_autocomplete(names)
+            .prompt()?;
+        let filter = filter.trim();
+        if filter.is_empty() {
+            break;
+        }
+
+        let refined: Vec<FileInfo> = files
+            .iter()
+            .filter(|file| score_name(&file.display_name, filter).is_some())
+            .cloned()
+            .collect();
+        if refined.is_empty() {
+            report!(
+                "{}",
+                styled(format!("No files match '{}'", filter).yellow())
+            );
+            continue;
+        }
+        files = refined;
+    }
+    Ok(files)
+}
+
+/// Suggests the best matching file names while the filter is typed
+#[derive(Clone)]
+struct NameSuggester(Vec<String>);
+
+impl Autocomplete for NameSuggester {
+    fn get_suggestions(&mut self, input: &str) -> Result<Vec<String>, CustomUserError> {
+        if input.trim().is_empty() {
+            return Ok(vec![]);
+        }
+        let mut matches: Vec<(i64, &String)> = self
+            .0
+            .iter()
+            .filter_map(|name| Some((score_name(name, input.trim())?, name)))
+            .collect();
+        matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        Ok(matches.into_iter().map(|(_, name)| name.clone()).collect())
+    }
+
+    fn get_completion(
+        &mut self,
+        _input: &str,
+        highlighted_suggestion: Option<String>,
+    ) -> Result<Replacement, CustomUserError> {
+        Ok(highlighted_suggestion)
+    }
+}
+
+/// Select a file, then show its preview and go back to the list unless confirmed
+fn select_file_with_preview(
+    files: Vec<ListEntry>,
+    page_size: Option<usize>,
+    verb: &str,
+) -> Result<FileInfo, Box<dyn std::error::Error>> {
+    loop {
+        let selected = select_file(files.clone(), page_size, verb)?;
+        if confirm_preview(&selected, verb)? {
+            return Ok(selected);
+        }
+    }
+}
+
+/// Show the preview of a selected file and ask whether to go ahead with it
+fn confirm_preview(selected: &FileInfo, verb: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    match preview_file(&selected.path) {
+        Ok(preview) => println!("{}", preview),
+        Err(e) => warning!(
+            "{}",
+            styled(format!("Could not preview file: {}", e).yellow())
+        ),
+    }
+
+    Ok(
+        Confirm::new(&format!("{} '{}'?", capitalize(verb), selected.name))
+            .with_default(true)
+            .with_help_message("Answer no to pick another file")
+            .prompt()?,
+    )
+}
+
+/// The list of `--watch`, which unlike `select_file` takes in each rescan while it
+/// is open. The highlighted file stays highlighted as files come and go, and an
+/// empty list or Escape goes back to waiting, as does `rescan_or_exit`.
+fn select_file_live(
+    files: Vec<FileInfo>,
+    stats: ScanStats,
+    config: &Config,
+    verb: &str,
+    scans: &mpsc::Receiver<WatchScan>,
+) -> Result<FileInfo, Box<dyn std::error::Error>> {
+    let mut list = LiveList {
+        entries: list_entries(files, config),
+        stats,
+        filter: String::new(),
+        cursor: 0,
+        offset: 0,
+    };
+    list.cursor = list.next_file(0, 1).unwrap_or(0);
+    let message = format!("Select a file to {}:", verb);
+    let mut screen = LiveScreen::start()?;
+
+    let mut redraw = true;
+    loop {
+        if redraw {
+            screen.draw(&list.lines(&message, list_page_size(config.page_size)))?;
+            redraw = false;
+        }
+
+        if event::poll(Duration::from_millis(100))? {
+            let Event::Key(key) = event::read()? else {
+                // Such as a resized terminal
+                redraw = true;
+                continue;
+            };
+            // Windows also reports releasing keys
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+            let control = key.modifiers.contains(KeyModifiers::CONTROL);
+            let page = list_page_size(config.page_size) as isize;
+            match key.code {
+                KeyCode::Char('c') if control => {
+                    return Err(InquireError::OperationInterrupted.into());
+                }
+                KeyCode::Esc => return Err(InquireError::OperationCanceled.into()),
+                KeyCode::Enter => {
+                    if let Some(file) = list.current() {
+                        let file = file.clone();
+                        screen.finish(&[
+                            styled(list.stats.to_string().cyan()),
+                            format!(
+                                "{} {} {}",
+                                styled("?".green()),
+                                message,
+                                styled(ListEntry::File(file.clone()).label().cyan())
+                            ),
+                        ])?;
+                        return Ok(file);
+                    }
+                }
+                KeyCode::Up => list.step(-1),
+                KeyCode::Char('p') if control => list.step(-1),
+                KeyCode::Down => list.step(1),
+                KeyCode::Char('n') if control => list.step(1),
+                KeyCode::PageUp => list.step(-page),
+                KeyCode::PageDown => list.step(page),
+                KeyCode::Home => list.step(isize::MIN),
+                KeyCode::End => list.step(isize::MAX),
+                KeyCode::Backspace => {
+                    list.filter.pop();
+                    list.cursor = list.next_file(0, 1).unwrap_or(0);
+                }
+                KeyCode::Char(c) if !control => {
+                    list.filter.push(c);
+                    list.cursor = list.next_file(0, 1).unwrap_or(0);
+                }
+                _ => continue,
+            }
+            redraw = true;
+        }
+
+        while let Ok(scan) = scans.try_recv() {
+            if !scan.announcements.is_empty() {
+                let lines: Vec<_> = scan
+                    .announcements
+                    .iter()
+                    .map(|announcement| styled(announcement.as_str().cyan()))
+                    .collect();
+                screen.print_above(&lines)?;
+            }
+            match scan.result {
+                Ok((files, _)) if files.is_empty() => {
+                    return Err(InquireError::OperationCanceled.into());
+                }
+                Ok((files, stats)) => {
+                    let entries = list_entries(files, config);
+                    list.replace(entries, stats);
+                }
+                Err(e) => {
+                    log::debug!("Rescanning during the watch failed: {}", e);
+                }
+            }
+            redraw = true;
+        }
+    }
+}
+
+/// The entries of the live list, with what is typed to filter them
+struct LiveList {
+    entries: Vec<ListEntry>,
+    stats: ScanStats,
+    filter: String,
+    // Index into the shown entries, always on a file when there is one
+    cursor: usize,
+    // The first shown entry on the page
+    offset: usize,
+}
+
+impl LiveList {
+    /// The entries that match the filter, best match first as in the other lists
+    fn shown(&self) -> Vec<&ListEntry> {
+        let filter = self.filter.trim();
+        let mut scored: Vec<(i64, &ListEntry)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| Some((score_file_name(filter, entry, "", index)?, entry)))
+            .collect();
+        if !filter.is_empty() {
+            scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        }
+        scored.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    fn current(&self) -> Option<&FileInfo> {
+        match self.shown().get(self.cursor) {
+            Some(ListEntry::File(file)) => Some(file),
+            _ => None,
+        }
+    }
+
+    /// The first file from `start` on in direction `step`, skipping group headers
+    fn next_file(&self, start: usize, step: isize) -> Option<usize> {
+        let shown = self.shown();
+        let mut index = start;
+        while let Some(entry) = shown.get(index) {
+            if let ListEntry::File(_) = entry {
+                return Some(index);
+            }
+            index = index.checked_add_signed(step)?;
+        }
+        None
+    }
+
+    /// Move the highlight by `rows`, stopping at either end of the list
+    fn step(&mut self, rows: isize) {
+        let last = self.shown().len().saturating_sub(1);
+        let target = self.cursor.saturating_add_signed(rows).min(last);
+        let direction = if rows < 0 { -1 } else { 1 };
+        self.cursor = self
+            .next_file(target, direction)
+            .or_else(|| self.next_file(target, -direction))
+            .unwrap_or(self.cursor);
+    }
+
+    /// Take in a rescan, keeping the highlight on the same file while it is listed
+    fn replace(&mut self, entries: Vec<ListEntry>, stats: ScanStats) {
+        let highlighted = self.current().map(|file| file.path.clone());
+        self.entries = entries;
+        self.stats = stats;
+        let shown = self.shown();
+        let kept = highlighted.and_then(|path| {
+            shown
+                .iter()
+                .position(|entry| matches!(entry, ListEntry::File(file) if file.path == path))
+        });
+        let last = shown.len().saturating_sub(1);
+        self.cursor = match kept {
+            Some(index) => index,
+            None => self
+                .next_file(self.cursor.min(last), 1)
+                .or_else(|| self.next_file(self.cursor.min(last), -1))
+                .unwrap_or(0),
+        };
+    }
+
+    /// The stats line, the prompt, a page of entries and the help line
+    fn lines(&mut self, message: &str, page_size: usize) -> Vec<String> {
+        let shown_len = self.shown().len();
+        if self.cursor < self.offset {
+            self.offset = self.cursor;
+        } else if self.cursor >= self.offset + page_size {
+            self.offset = self.cursor + 1 - page_size;
+        }
+        self.offset = self.offset.min(shown_len.saturating_sub(page_size));
+
+        let mut lines = vec![
+            styled(self.stats.to_string().cyan()),
+            format!("{} {} {}", styled("?".green()), message, self.filter),
+        ];
+        let shown = self.shown();
+        for (index, entry) in shown.iter().enumerate().skip(self.offset).take(page_size) {
+            if index == self.cursor {
+                lines.push(styled(format!("> {}", entry).cyan()));
+            } else {
+                lines.push(format!("  {}", entry));
+            }
+        }
+        if shown.is_empty() {
+            lines.push(format!("  No files match '{}'", self.filter));
+        }
+        lines.push(styled(
+            "[Use arrow keys to navigate, type to search, press Enter to select]".cyan(),
+        ));
+        lines
     }
-    
-    println!(
+}
+
+/// The terminal while the live list is shown: raw mode for reading keys, and lines
+/// clipped rather than wrapped so the list can be erased to draw it again
+struct LiveScreen {
+    out: io::Stdout,
+    // Lines drawn last time, to erase
+    drawn: usize,
+}
+
+impl LiveScreen {
+    fn start() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut out = io::stdout();
+        execute!(out, terminal::DisableLineWrap, cursor::Hide)?;
+        Ok(LiveScreen { out, drawn: 0 })
+    }
+
+    fn erase(&mut self) -> io::Result<()> {
+        if self.drawn > 1 {
+            queue!(self.out, cursor::MoveUp(self.drawn as u16 - 1))?;
+        }
+        if self.drawn > 0 {
+            queue!(
+                self.out,
+                cursor::MoveToColumn(0),
+                terminal::Clear(terminal::ClearType::FromCursorDown)
+            )?;
+        }
+        self.drawn = 0;
+        Ok(())
+    }
+
+    fn draw(&mut self, lines: &[String]) -> io::Result<()> {
+        self.erase()?;
+        write!(self.out, "{}", lines.join("\r\n"))?;
+        self.drawn = lines.len();
+        self.out.flush()
+    }
+
+    /// Print lines that stay above the list, which is drawn again below them
+    fn print_above(&mut self, lines: &[String]) -> io::Result<()> {
+        self.erase()?;
+        for line in lines {
+            write!(self.out, "{}\r\n", line)?;
+        }
+        self.out.flush()
+    }
+
+    /// Replace the list with what stays on screen once it is closed
+    fn finish(&mut self, lines: &[String]) -> io::Result<()> {
+        self.print_above(lines)
+    }
+}
+
+impl Drop for LiveScreen {
+    fn drop(&mut self) {
+        let _ = self.erase();
+        let _ = execute!(self.out, terminal::EnableLineWrap, cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn select_files(
+    files: Vec<ListEntry>,
+    page_size: Option<usize>,
+    verb: &str,
+) -> Result<Vec<FileInfo>, Box<dyn std::error::Error>> {
+    let help = if ASCII.load(Ordering::Relaxed) {
+        "Use arrow keys to navigate, Space to toggle, Right to select all, Enter to confirm"
+    } else {
+        "Use arrow keys to navigate, Space to toggle, → to select all, Enter to confirm"
+    };
+    let message = format!("Select files to {}:", verb);
+    let selected = MultiSelect::new(&message, files)
+        .with_help_message(help)
+        .with_formatter(&|list| {
+            list.iter()
+                .filter(|x| matches!(x.value, ListEntry::File(_)))
+                .map(|x| x.value.label())
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .with_scorer(&score_file_name)
+        .with_page_size(list_page_size(page_size))
+        .prompt()?;
+
+    // Toggled group headers select nothing
+    Ok(selected
+        .into_iter()
+        .filter_map(|entry| match entry {
+            ListEntry::File(file) => Some(file),
+            ListEntry::Header { .. } => None,
+        })
+        .collect())
+}
+
+fn copy_path_to_clipboard(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::path::absolute(path)?.to_string_lossy().to_string();
+    arboard::Clipboard::new()?.set_text(path.clone())?;
+
+    report!(
         "{}",
-        format!(
-            "Successfully moved '{}' to current directory",
-            file_info.name
-        )
-        .green()
+        styled(format!("Copied '{}' to the clipboard", path).green())
     );
-
     Ok(())
 }
 
-fn format_size(bytes: u64) -> String {
-    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
-    let mut size = bytes as f64;
-    let mut unit_index = 0;
-
-    while size >= 1024.0 && unit_index < UNITS.len() - 1 {
-        size /= 1024.0;
-        unit_index += 1;
+/// Reveal the target directory in the platform file manager when `--open` is set.
+/// A failure here only warns, the move itself already succeeded.
+fn open_target_dir(options: &MoveOptions) {
+    if !options.open_after || options.dry_run {
+        return;
     }
 
-    if unit_index == 0 {
-        format!("{}B", bytes)
+    let program = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
     } else {
-        format!("{:.1}{}", size, UNITS[unit_index])
+        "xdg-open"
+    };
+
+    if let Err(e) = process::Command::new(program)
+        .arg(&options.target_dir)
+        .spawn()
+    {
+        warning!(
+            "{}",
+            styled(
+                format!(
+                    "Failed to open {} with {}: {}",
+                    describe_dir(&options.target_dir),
+                    program,
+                    e
+                )
+                .yellow()
+            )
+        );
     }
 }
+
+/// Like `last_destination`, telling the user the remembered directory is used
+fn remembered_destination(file: &FileInfo) -> Option<PathBuf> {
+    let destination = last_destination(file)?;
+    let source_dir = file.path.parent().unwrap_or(&file.path);
+
+    report!(
+        "{}",
+        styled(
+            format!(
+                "Using the last destination for files from '{}': '{}'",
+                source_dir.display(),
+                destination.display()
+            )
+            .cyan()
+        )
+    );
+    Some(destination)
+}
+
+/// Ask where the files should go: the last used destination first, then the
+/// configured destinations, then the default target directory
+fn pick_destination(
+    config: &Config,
+    default_target: &Path,
+    last: Option<PathBuf>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut choices: Vec<(String, PathBuf)> = Vec::new();
+    if let Some(last) = last {
+        choices.push((format!("{} (last used)", last.display()), last));
+    }
+    for destination in &config.destinations {
+        let path = expand_path(destination);
+        choices.push((path.display().to_string(), path));
+    }
+    choices.push((describe_dir(default_target), default_target.to_path_buf()));
+
+    // Drop repeats, keeping the first occurrence with its label
+    let mut seen = HashSet::new();
+    choices.retain(|(_, path)| seen.insert(path.clone()));
+
+    let labels: Vec<&str> = choices.iter().map(|(label, _)| label.as_str()).collect();
+    let index = Select::new("Move to:", labels).raw_prompt()?.index;
+
+    Ok(choices.swap_remove(index).1)
+}